@@ -0,0 +1,179 @@
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Where a character sits on its page and how to place it, in the font's own pixels
+pub struct BitmapGlyph {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub x_offset: f32, // From the pen position to the left edge of the glyph
+    pub y_offset: f32, // From the top of the line to the top edge of the glyph
+    pub x_advance: f32,
+    pub page: usize,
+}
+
+// AngelCode BMFont in its text format (.fnt), as exported by BMFont, Hiero, Littera and friends
+pub struct BitmapFont {
+    pub size: f32, // Size the font was drawn at, text spans get integer multiples of it
+    pub line_height: f32,
+    pub base: f32, // Top of the line to the baseline
+    pub page_width: f32,
+    pub page_height: f32,
+    pub page_paths: Vec<PathBuf>,
+    pub page_texture_ids: Vec<usize>, // Filled in by the renderer once the pages are registered
+    glyphs: HashMap<char, BitmapGlyph>,
+    kernings: HashMap<(char, char), f32>,
+}
+
+impl BitmapFont {
+    pub fn load(path: &str) -> Result<Self> {
+        let src = std::fs::read_to_string(path).with_context(|| format!("Reading {}", path))?;
+        // Page files are relative to the .fnt
+        let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        Self::parse(&src, directory).with_context(|| format!("Parsing {}", path))
+    }
+
+    pub fn parse(src: &str, directory: &Path) -> Result<Self> {
+        let mut font = Self {
+            size: 0.,
+            line_height: 0.,
+            base: 0.,
+            page_width: 1.,
+            page_height: 1.,
+            page_paths: Vec::new(),
+            page_texture_ids: Vec::new(),
+            glyphs: HashMap::new(),
+            kernings: HashMap::new(),
+        };
+
+        for (line_index, line) in src.lines().enumerate() {
+            let mut tokens = tokenize(line).into_iter();
+            let tag = match tokens.next() {
+                Some(tag) => tag,
+                None => continue,
+            };
+            let attributes: HashMap<String, String> = tokens
+                .filter_map(|token| {
+                    let (key, value) = token.split_once('=')?;
+                    Some((key.to_owned(), value.trim_matches('"').to_owned()))
+                })
+                .collect();
+            let number = |key: &str| -> Result<f32> {
+                let value = attributes.get(key).ok_or_else(|| anyhow!("Line {}: {} is missing {}", line_index + 1, tag, key))?;
+                value.parse::<f32>().with_context(|| format!("Line {}: {} is not a number", line_index + 1, key))
+            };
+
+            match tag.as_str() {
+                // Negative when the font was exported with "match char height"
+                "info" => font.size = number("size")?.abs(),
+                "common" => {
+                    font.line_height = number("lineHeight")?;
+                    font.base = number("base")?;
+                    font.page_width = number("scaleW")?;
+                    font.page_height = number("scaleH")?;
+                },
+                "page" => {
+                    let id = number("id")? as usize;
+                    let file = attributes.get("file").ok_or_else(|| anyhow!("Line {}: page is missing file", line_index + 1))?;
+                    if font.page_paths.len() <= id {
+                        font.page_paths.resize(id + 1, PathBuf::new());
+                    }
+                    font.page_paths[id] = directory.join(file);
+                },
+                "char" => {
+                    let id = number("id")? as u32;
+                    if let Some(c) = std::char::from_u32(id) {
+                        font.glyphs.insert(c, BitmapGlyph {
+                            x: number("x")?,
+                            y: number("y")?,
+                            width: number("width")?,
+                            height: number("height")?,
+                            x_offset: number("xoffset")?,
+                            y_offset: number("yoffset")?,
+                            x_advance: number("xadvance")?,
+                            page: number("page")? as usize,
+                        });
+                    }
+                },
+                "kerning" => {
+                    let first = std::char::from_u32(number("first")? as u32);
+                    let second = std::char::from_u32(number("second")? as u32);
+                    if let (Some(first), Some(second)) = (first, second) {
+                        font.kernings.insert((first, second), number("amount")?);
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        if font.page_paths.is_empty() || font.page_paths.iter().any(|path| path.as_os_str().is_empty()) {
+            return Err(anyhow!("Missing page definitions"));
+        }
+        if let Some(glyph) = font.glyphs.values().find(|glyph| glyph.page >= font.page_paths.len()) {
+            return Err(anyhow!("Glyph on page {} but there are only {} pages", glyph.page, font.page_paths.len()));
+        }
+        if font.size == 0. {
+            font.size = font.line_height;
+        }
+        Ok(font)
+    }
+
+    // Only whole multiples of the original size keep the pixels square
+    pub fn scale(&self, size: f32) -> f32 {
+        if self.size <= 0. {
+            return 1.;
+        }
+        (size / self.size).round().max(1.)
+    }
+
+    // Characters the font doesn't have show up as '?' if it has that, otherwise they're skipped
+    pub fn glyph(&self, c: char) -> Option<&BitmapGlyph> {
+        self.glyphs.get(&c).or_else(|| self.glyphs.get(&'?'))
+    }
+
+    pub fn kerning(&self, first: char, second: char) -> f32 {
+        self.kernings.get(&(first, second)).copied().unwrap_or(0.)
+    }
+
+    pub fn text_width(&self, text: &str, scale: f32) -> f32 {
+        let mut width = 0.;
+        let mut previous: Option<char> = None;
+        for c in text.chars() {
+            if let Some(previous) = previous {
+                width += self.kerning(previous, c);
+            }
+            if let Some(glyph) = self.glyph(c) {
+                width += glyph.x_advance;
+            }
+            previous = Some(c);
+        }
+        width * scale
+    }
+}
+
+// Splits on whitespace, except inside quotes - face names and file names can have spaces
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            },
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            },
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
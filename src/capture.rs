@@ -0,0 +1,498 @@
+use anyhow::{anyhow, bail, Context, Result};
+use core::num::NonZeroU32;
+use futures::FutureExt;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::future::Future;
+use std::io::{Seek, SeekFrom, Write};
+use std::pin::Pin;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// Lower is prettier but way slower, and we encode while the game is running
+const GIF_ENCODING_SPEED: i32 = 20;
+const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+pub enum RecordingMode {
+    // Dumps the next `count` frames as numbered PNGs into `directory`
+    Frames { count: u32, directory: String },
+    // Encodes the next `count` frames into an animated GIF (stops early on stop_recording)
+    Gif { count: u32, path: String },
+    // Same as Gif but an animated PNG, bigger files without the GIF's 256 colors
+    Apng { count: u32, path: String },
+}
+
+// Where recorded frames end up, owned by the recording's encoder thread
+enum FrameSink {
+    Frames { directory: String, frames_saved: u32 },
+    Gif(GifEncoder<File>),
+    Apng(ApngEncoder),
+}
+
+impl FrameSink {
+    fn save(&mut self, image: image::RgbaImage, frame_time: Duration) -> Result<()> {
+        match self {
+            FrameSink::Frames { directory, frames_saved } => {
+                let path = format!("{}/frame_{:05}.png", directory, frames_saved);
+                image.save(&path).with_context(|| format!("Saving frame {}", path))?;
+                *frames_saved += 1;
+            },
+            FrameSink::Gif(encoder) => {
+                let delay = image::Delay::from_saturating_duration(frame_time);
+                encoder.encode_frame(image::Frame::from_parts(image, 0, 0, delay))?;
+            },
+            FrameSink::Apng(encoder) => encoder.encode_frame(&image, frame_time)?,
+        }
+        Ok(())
+    }
+
+    // Dropping the gif encoder writes the gif trailer, the apng one has to go back and fill in the frame count
+    fn finish(self) -> Result<()> {
+        match self {
+            FrameSink::Apng(encoder) => encoder.finish(),
+            _ => Ok(()),
+        }
+    }
+}
+
+struct Recording {
+    mode: RecordingMode,
+    frames_captured: u32,
+    last_frame_time: Instant,
+    frames: mpsc::Sender<(image::RgbaImage, Duration)>, // To the encoder thread, which stops once this is dropped
+    encoder_thread: JoinHandle<Result<()>>,
+}
+
+// Offscreen copy of the frame - swap chain frames can't be copied from, so frames we want to keep get drawn here
+// instead and copied onto the swap chain frame after
+pub struct CaptureTarget {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub id: u64, // Every new target gets a new one, so whatever reads from it knows when to look again
+    width: u32,
+    height: u32,
+}
+
+// A captured frame on its way back from the GPU, and where it goes once it's there
+struct PendingFrame {
+    buffer: wgpu::Buffer,
+    mapping: Pin<Box<dyn Future<Output = std::result::Result<(), wgpu::BufferAsyncError>> + Send>>,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    screenshot_paths: Vec<String>,
+    frame_time: Option<Duration>, // Since the recorded frame before, None when it isn't recorded
+}
+
+pub struct FrameCapture {
+    target: Option<CaptureTarget>,
+    targets_created: u64,
+    screenshot_requests: Vec<String>,
+    recording: Option<Recording>,
+    pending_frames: VecDeque<PendingFrame>, // Oldest first
+}
+
+impl FrameCapture {
+    pub fn new() -> Self {
+        Self { target: None, targets_created: 0, screenshot_requests: Vec::new(), recording: None, pending_frames: VecDeque::new() }
+    }
+
+    pub fn request_screenshot(&mut self, path: &str) {
+        self.screenshot_requests.push(path.to_owned());
+    }
+
+    pub fn start_recording(&mut self, device: &wgpu::Device, mode: RecordingMode) -> Result<()> {
+        // Finish whatever was being recorded before so the file doesn't end up half written
+        self.stop_recording(device);
+
+        let mut sink = match &mode {
+            RecordingMode::Frames { directory, .. } => {
+                std::fs::create_dir_all(directory).context("Creating frame dump directory")?;
+                FrameSink::Frames { directory: directory.clone(), frames_saved: 0 }
+            },
+            RecordingMode::Gif { path, .. } => {
+                let file = File::create(path).context("Creating gif file")?;
+                let mut encoder = GifEncoder::new_with_speed(file, GIF_ENCODING_SPEED);
+                encoder.set_repeat(Repeat::Infinite)?;
+                FrameSink::Gif(encoder)
+            },
+            RecordingMode::Apng { path, .. } => {
+                let file = File::create(path).context("Creating apng file")?;
+                FrameSink::Apng(ApngEncoder::new(file))
+            },
+        };
+        // Encoding takes longer than a frame, so it happens next to the game instead of holding it up
+        let (frames, received_frames) = mpsc::channel::<(image::RgbaImage, Duration)>();
+        let encoder_thread = thread::spawn(move || {
+            for (image, frame_time) in received_frames {
+                sink.save(image, frame_time)?;
+            }
+            sink.finish()
+        });
+        self.recording = Some(Recording { mode, frames_captured: 0, last_frame_time: Instant::now(), frames, encoder_thread });
+        Ok(())
+    }
+
+    // Waits for the frames still on their way back from the GPU and for the encoder to get through them
+    pub fn stop_recording(&mut self, device: &wgpu::Device) {
+        let recording = match self.recording.take() {
+            Some(recording) => recording,
+            None => return,
+        };
+        if let Err(error) = self.save_frames(device, wgpu::Maintain::Wait, Some(&recording)) {
+            println!("Couldn't save captured frame: {:?}", error);
+        }
+        drop(recording.frames);
+        let result = recording.encoder_thread.join().unwrap_or_else(|_| Err(anyhow!("Encoder thread panicked")));
+        if let Err(error) = result {
+            println!("Couldn't finish the recording: {:?}", error);
+        }
+        match recording.mode {
+            RecordingMode::Frames { directory, .. } => println!("Recorded {} frames to {}", recording.frames_captured, directory),
+            RecordingMode::Gif { path, .. } | RecordingMode::Apng { path, .. } => println!("Recorded {} frames to {}", recording.frames_captured, path),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn wants_frame(&self) -> bool {
+        !self.screenshot_requests.is_empty() || self.recording.is_some()
+    }
+    // Hands out the offscreen target (recreated if the swap chain changed size); give it back with put_target
+    pub fn take_target(&mut self, device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor) -> CaptureTarget {
+        match self.target.take() {
+            Some(target) if target.width == sc_desc.width && target.height == sc_desc.height => target,
+            _ => {
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("capture_texture"),
+                    size: wgpu::Extent3d {
+                        width: sc_desc.width,
+                        height: sc_desc.height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: sc_desc.format,
+                    usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_SRC,
+                });
+                let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                self.targets_created += 1;
+                CaptureTarget { texture, view, id: self.targets_created, width: sc_desc.width, height: sc_desc.height }
+            }
+        }
+    }
+
+    pub fn put_target(&mut self, target: CaptureTarget) {
+        self.target = Some(target);
+    }
+
+    // Records a copy of the target into a mappable buffer; read it back with save_frame after the encoder is submitted
+    pub fn copy_to_buffer(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) -> Option<wgpu::Buffer> {
+        let target = self.target.as_ref()?;
+        let padded_bytes_per_row = padded_bytes_per_row(target.width);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("capture_buffer"),
+            size: (padded_bytes_per_row * target.height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &target.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(NonZeroU32::new(padded_bytes_per_row).unwrap()),
+                    rows_per_image: Some(NonZeroU32::new(target.height).unwrap()),
+                },
+            },
+            wgpu::Extent3d {
+                width: target.width,
+                height: target.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        Some(buffer)
+    }
+
+    // Starts mapping a buffer from copy_to_buffer once its encoder got submitted. The frame gets saved by save_ready_frames
+    // whenever the GPU is done with it, for the screenshots requested and the recording running until now
+    pub fn read_back(&mut self, device: &wgpu::Device, buffer: wgpu::Buffer, format: wgpu::TextureFormat) {
+        let (width, height) = match &self.target {
+            Some(target) => (target.width, target.height),
+            None => return,
+        };
+        let mut finished = false;
+        let frame_time = self.recording.as_mut().map(|recording| {
+            let frame_time = recording.last_frame_time.elapsed();
+            recording.last_frame_time = Instant::now();
+            recording.frames_captured += 1;
+            finished = match &recording.mode {
+                RecordingMode::Frames { count, .. } | RecordingMode::Gif { count, .. } | RecordingMode::Apng { count, .. } => recording.frames_captured >= *count,
+            };
+            frame_time
+        });
+        let mapping = Box::pin(buffer.slice(..).map_async(wgpu::MapMode::Read));
+        let screenshot_paths = self.screenshot_requests.drain(..).collect();
+        self.pending_frames.push_back(PendingFrame { buffer, mapping, width, height, format, screenshot_paths, frame_time });
+        if finished {
+            self.stop_recording(device);
+        }
+    }
+
+    // Hands whatever frames made it back from the GPU to the threads saving them, without waiting for the rest
+    pub fn save_ready_frames(&mut self, device: &wgpu::Device) -> Result<()> {
+        let recording = self.recording.take();
+        let result = self.save_frames(device, wgpu::Maintain::Poll, recording.as_ref());
+        self.recording = recording;
+        result
+    }
+
+    fn save_frames(&mut self, device: &wgpu::Device, maintain: wgpu::Maintain, recording: Option<&Recording>) -> Result<()> {
+        device.poll(maintain);
+        while let Some(oldest) = self.pending_frames.front_mut() {
+            // Frames come back in the order they were submitted
+            let mapped = match maintain {
+                wgpu::Maintain::Wait => futures::executor::block_on(&mut oldest.mapping),
+                wgpu::Maintain::Poll => match (&mut oldest.mapping).now_or_never() {
+                    Some(mapped) => mapped,
+                    None => break,
+                },
+            };
+            let pending = self.pending_frames.pop_front().unwrap();
+            mapped.context("Mapping capture buffer")?;
+            let image = read_buffer(&pending.buffer, pending.width, pending.height, pending.format)?;
+
+            if !pending.screenshot_paths.is_empty() {
+                let (image, screenshot_paths) = (image.clone(), pending.screenshot_paths);
+                thread::spawn(move || {
+                    for path in screenshot_paths {
+                        match image.save(&path) {
+                            Ok(()) => println!("Saved screenshot to {}", path),
+                            Err(error) => println!("Couldn't save screenshot {}: {:?}", path, error),
+                        }
+                    }
+                });
+            }
+            // The encoder thread is gone when it ran into an error, which it reports when the recording stops
+            if let (Some(frame_time), Some(recording)) = (pending.frame_time, recording) {
+                let _ = recording.frames.send((image, frame_time));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Animated PNG written a frame at a time. The image crate can't write these, so every frame gets encoded as a plain
+// PNG and its image data moved over into the animation. How many frames there are is only known at the end,
+// finish goes back and writes it into the header
+struct ApngEncoder {
+    file: File,
+    size: Option<(u32, u32)>, // Of the first frame, the ones after have to match
+    frames: u32,
+    sequence_number: u32, // Of the next fcTL or fdAT chunk, they're numbered together
+    actl_position: u64,
+}
+
+impl ApngEncoder {
+    fn new(file: File) -> Self {
+        Self { file, size: None, frames: 0, sequence_number: 0, actl_position: 0 }
+    }
+
+    fn encode_frame(&mut self, image: &image::RgbaImage, delay: Duration) -> Result<()> {
+        let (width, height) = image.dimensions();
+        let mut png = Vec::new();
+        PngEncoder::new_with_quality(&mut png, CompressionType::Fast, FilterType::Sub)
+            .encode(image.as_raw(), width, height, image::ColorType::Rgba8)?;
+        let chunks = png_chunks(&png)?;
+
+        match self.size {
+            None => {
+                let (_, ihdr) = chunks.iter().find(|(kind, _)| kind == b"IHDR").context("Encoded frame has no IHDR")?;
+                self.file.write_all(&PNG_SIGNATURE)?;
+                write_chunk(&mut self.file, b"IHDR", ihdr)?;
+                self.actl_position = self.file.stream_position()?;
+                write_chunk(&mut self.file, b"acTL", &actl(0))?;
+                self.size = Some((width, height));
+            },
+            Some(size) if size != (width, height) => bail!("Frame is {}x{}, the recording started at {}x{}", width, height, size.0, size.1),
+            Some(_) => {},
+        }
+
+        let mut fctl = Vec::with_capacity(26);
+        fctl.extend_from_slice(&self.sequence_number.to_be_bytes());
+        fctl.extend_from_slice(&width.to_be_bytes());
+        fctl.extend_from_slice(&height.to_be_bytes());
+        fctl.extend_from_slice(&[0; 8]); // Offset
+        fctl.extend_from_slice(&(delay.as_millis().min(u16::MAX as u128) as u16).to_be_bytes());
+        fctl.extend_from_slice(&1000u16.to_be_bytes());
+        fctl.extend_from_slice(&[0, 0]); // Nothing to dispose or blend, every frame replaces the whole picture
+        write_chunk(&mut self.file, b"fcTL", &fctl)?;
+        self.sequence_number += 1;
+
+        // The first frame doubles as the still image for viewers that don't know APNG
+        for (_, data) in chunks.iter().filter(|(kind, _)| kind == b"IDAT") {
+            if self.frames == 0 {
+                write_chunk(&mut self.file, b"IDAT", data)?;
+            } else {
+                let mut fdat = Vec::with_capacity(4 + data.len());
+                fdat.extend_from_slice(&self.sequence_number.to_be_bytes());
+                fdat.extend_from_slice(data);
+                write_chunk(&mut self.file, b"fdAT", &fdat)?;
+                self.sequence_number += 1;
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        if self.frames == 0 {
+            return Ok(());
+        }
+        write_chunk(&mut self.file, b"IEND", &[])?;
+        self.file.seek(SeekFrom::Start(self.actl_position))?;
+        write_chunk(&mut self.file, b"acTL", &actl(self.frames))?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+// Frame count, then 0 plays for looping forever
+fn actl(frames: u32) -> [u8; 8] {
+    let mut data = [0; 8];
+    data[..4].copy_from_slice(&frames.to_be_bytes());
+    data
+}
+
+fn write_chunk(writer: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> Result<()> {
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    writer.write_all(&crc.finalize().to_be_bytes())?;
+    Ok(())
+}
+
+// Type and data of every chunk in a PNG file, in order
+fn png_chunks(png: &[u8]) -> Result<Vec<([u8; 4], &[u8])>> {
+    if !png.starts_with(&PNG_SIGNATURE) {
+        bail!("Not a PNG");
+    }
+    let mut chunks = Vec::new();
+    let mut rest = &png[PNG_SIGNATURE.len()..];
+    while !rest.is_empty() {
+        let header = rest.get(..8).context("PNG chunk header runs past the end")?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let kind = [header[4], header[5], header[6], header[7]];
+        let data = rest.get(8..8 + length).context("PNG chunk runs past the end")?;
+        chunks.push((kind, data));
+        rest = rest.get(12 + length..).context("PNG chunk runs past the end")?;
+    }
+    Ok(chunks)
+}
+
+fn padded_bytes_per_row(width: u32) -> u32 {
+    let unpadded = 4 * width;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    (unpadded + align - 1) / align * align
+}
+
+// The buffer has to be mapped already
+fn read_buffer(buffer: &wgpu::Buffer, width: u32, height: u32, format: wgpu::TextureFormat) -> Result<image::RgbaImage> {
+    let slice = buffer.slice(..);
+    let padded_bytes_per_row = padded_bytes_per_row(width) as usize;
+    let mut pixels: Vec<u8> = Vec::with_capacity((4 * width * height) as usize);
+    {
+        let data = slice.get_mapped_range();
+        for row in data.chunks(padded_bytes_per_row) {
+            pixels.extend_from_slice(&row[..4 * width as usize]);
+        }
+    }
+    buffer.unmap();
+
+    // The swap chain is usually BGRA, image wants RGBA
+    match format {
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        },
+        _ => {}
+    }
+    // Nothing is supposed to be see-through in a screenshot
+    for pixel in pixels.chunks_mut(4) {
+        pixel[3] = 255;
+    }
+
+    image::RgbaImage::from_raw(width, height, pixels).context("Capture buffer has the wrong size")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::AnimationDecoder;
+    use image::codecs::png::PngDecoder;
+
+    #[test]
+    fn apng_recording_reads_back_frame_by_frame() {
+        let path = std::env::temp_dir().join(format!("wgpu_simple2d_capture_test_{}.png", std::process::id()));
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+        let mut encoder = ApngEncoder::new(File::create(&path).unwrap());
+        for (i, color) in colors.iter().enumerate() {
+            let image = image::RgbaImage::from_pixel(5, 3, image::Rgba(*color));
+            encoder.encode_frame(&image, Duration::from_millis(20 * (i as u64 + 1))).unwrap();
+        }
+        encoder.finish().unwrap();
+
+        let decoder = PngDecoder::new(File::open(&path).unwrap()).unwrap();
+        assert!(decoder.is_apng());
+        let frames = decoder.apng().into_frames().collect_frames().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(frames.len(), colors.len());
+        for (i, (frame, color)) in frames.iter().zip(colors.iter()).enumerate() {
+            assert_eq!(frame.buffer().dimensions(), (5, 3));
+            assert!(frame.buffer().pixels().all(|pixel| pixel.0 == *color), "frame {} has the wrong colors", i);
+            assert_eq!(frame.delay().numer_denom_ms(), (20 * (i as u32 + 1), 1));
+        }
+    }
+
+    #[test]
+    fn apng_frames_have_to_keep_the_size_of_the_first() {
+        let path = std::env::temp_dir().join(format!("wgpu_simple2d_capture_size_test_{}.png", std::process::id()));
+        let mut encoder = ApngEncoder::new(File::create(&path).unwrap());
+        encoder.encode_frame(&image::RgbaImage::new(4, 4), Duration::from_millis(10)).unwrap();
+        let result = encoder.encode_frame(&image::RgbaImage::new(8, 4), Duration::from_millis(10));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn frame_dumps_are_numbered_in_order() {
+        let directory = std::env::temp_dir().join(format!("wgpu_simple2d_frames_test_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let mut sink = FrameSink::Frames { directory: directory.to_str().unwrap().to_owned(), frames_saved: 0 };
+        for _ in 0..2 {
+            sink.save(image::RgbaImage::new(2, 2), Duration::from_millis(10)).unwrap();
+        }
+        sink.finish().unwrap();
+        let saved = [directory.join("frame_00000.png").exists(), directory.join("frame_00001.png").exists()];
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(saved, [true, true]);
+    }
+}
@@ -1,4 +1,5 @@
 use std::any;
+use crate::renderer::{RenderLayer, NineSlice};
 
 pub struct Name<'a> {
     pub name: &'a str
@@ -9,7 +10,41 @@ pub struct Sprite {
     pub render: bool,
     pub width_normalized: f32,
     pub height_normalized: f32,
-    pub z: u32 // TODO: Move Z elsewhere, proly some other component
+    pub z: u32, // TODO: Move Z elsewhere, proly some other component
+    pub layer: RenderLayer,
+    pub tint: [f32; 4],
+    pub opacity: f32,
+    pub rotation: f32, // Radians, clockwise
+    pub pivot: [f32; 2], // Relative to the sprite, [0.5, 0.5] rotates and scales around the center
+    pub scale: [f32; 2],
+    pub vert_mirror: bool,
+    pub material_id: Option<usize>, // None draws with the default sprite shader
+    pub material_params: [f32; 4], // Meaning depends on the material, e.g. how far along a dissolve is
+    pub normal_map_id: Option<usize>, // Register with register_normal_map
+    pub nine_slice: Option<NineSlice>, // For panels stretched to width_normalized x height_normalized
+}
+
+impl Default for Sprite {
+    fn default() -> Self {
+        Self {
+            texture_id: 0,
+            render: true,
+            width_normalized: 0.,
+            height_normalized: 0.,
+            z: 0,
+            layer: RenderLayer::World,
+            tint: [1., 1., 1., 1.],
+            opacity: 1.,
+            rotation: 0.,
+            pivot: [0.5, 0.5],
+            scale: [1., 1.],
+            vert_mirror: false,
+            material_id: None,
+            material_params: [0., 0., 0., 0.],
+            normal_map_id: None,
+            nine_slice: None,
+        }
+    }
 }
 
 pub struct Health {
@@ -21,7 +56,7 @@ pub struct Animation<'a> {
     pub running: bool,
     pub sprites: Vec<Sprite>,
     pub time_per_frame_ms: u32,
-    pub time_since_last_frame: u32,
+    pub time_since_last_frame: f32,
     pub current_frame_index: usize,
 }
 
@@ -31,6 +66,7 @@ pub struct AnimationMap<'a> {
     pub current_animation_name: &'a str,
 }
 
+#[derive(Clone, Copy)]
 pub struct Position {
     pub x: f32,
     pub y: f32,
@@ -47,7 +83,7 @@ pub struct Velocity {
 
 pub struct Points {
     pub points: u32,
-    pub time_since_last_point_change_ms: u32,
+    pub time_since_last_point_change_ms: f32,
 }
 
 pub struct RigidBody {
@@ -98,4 +134,110 @@ pub enum EntityType {
 
 pub struct MarkedForDeletion {
     pub marked: bool,
-}
\ No newline at end of file
+}
+
+pub struct Particle {
+    pub x: f32,
+    pub y: f32,
+    pub vel_x: f32,
+    pub vel_y: f32,
+    pub gravity: f32,
+    pub age_ms: f32,
+    pub lifetime_ms: u32,
+}
+
+// Ranges are [min, max] and get picked from randomly for every spawned particle
+pub struct ParticleEmitter {
+    pub texture_id: usize,
+    pub emitting: bool,
+    pub spawn_rate: f32, // Particles per second while emitting
+    pub burst: u32, // Spawned all at once on the next update, then reset to 0
+    pub despawn_when_done: bool, // Mark the entity for deletion once it stops emitting and all particles died
+    pub offset: [f32; 2], // Spawn point relative to the entity position
+    pub spawn_area: [f32; 2], // Width and height of the area around the spawn point
+    pub lifetime_ms: [u32; 2],
+    pub vel_x: [f32; 2],
+    pub vel_y: [f32; 2],
+    pub gravity: [f32; 2],
+    pub start_color: [f32; 4],
+    pub end_color: [f32; 4],
+    pub start_size: [f32; 2], // Normalized width and height
+    pub end_size: [f32; 2],
+    pub layer: RenderLayer,
+    pub z: u32,
+    pub particles: Vec<Particle>,
+    pub spawn_accumulator: f32,
+    pub rng_state: u32,
+}
+
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        Self {
+            texture_id: 0,
+            emitting: true,
+            spawn_rate: 0.,
+            burst: 0,
+            despawn_when_done: false,
+            offset: [0., 0.],
+            spawn_area: [0., 0.],
+            lifetime_ms: [1000, 1000],
+            vel_x: [0., 0.],
+            vel_y: [0., 0.],
+            gravity: [0., 0.],
+            start_color: [1., 1., 1., 1.],
+            end_color: [1., 1., 1., 0.],
+            start_size: [0., 0.],
+            end_size: [0., 0.],
+            layer: RenderLayer::World,
+            z: 0,
+            particles: Vec::new(),
+            spawn_accumulator: 0.,
+            rng_state: 0x9E37_79B9,
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VerticalAnchor {
+    Top,
+    Center,
+    Bottom,
+}
+
+// Background art drawn behind the world, moving slower than it to fake depth
+pub struct ParallaxLayer {
+    pub texture_id: usize, // Register with register_tiling_texture so repeating works
+    pub scroll_factor: [f32; 2], // 0 stays put like the sky, 1 moves along with the world
+    pub tile_size: [f32; 2], // Normalized size of one repetition of the texture
+    pub repeat_x: bool,
+    pub repeat_y: bool,
+    pub vertical_anchor: VerticalAnchor,
+    pub offset: [f32; 2], // Nudges the layer away from its anchor
+    pub z: u32, // Higher is closer to the world
+}
+
+pub struct Camera {
+    pub x: f32,
+    pub y: f32,
+}
+
+// Lights up everything around the entity's position. Only does something in scenes with lighting, see AmbientLight
+pub struct PointLight {
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub radius: f32, // Fraction of the screen height
+    pub height: f32, // Above the screen, low lights make normal maps stand out more
+    pub offset: [f32; 2], // From the entity's position
+}
+
+// Base light level of the scene; a scene with lights but no ambient one is lit at full brightness plus the lights
+pub struct AmbientLight {
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+// Keeps turning the entity's sprite, radians per second
+pub struct Spin {
+    pub speed: f32,
+}
@@ -0,0 +1,168 @@
+use crate::renderer::{RenderableText, TextSpan, TextOutline};
+use crate::stats::RenderStats;
+use wgpu::util::DeviceExt;
+
+// Segments a circle gets approximated with
+const CIRCLE_SEGMENTS: usize = 24;
+
+// Same layout as the inputs of debug.vert
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct DebugVertex {
+    position: [f32; 2],
+    color: [f32; 4],
+}
+unsafe impl bytemuck::Pod for DebugVertex{}
+unsafe impl bytemuck::Zeroable for DebugVertex{}
+
+// Immediate mode shapes for figuring out what the game is doing. Whatever gets queued shows up on top of the next
+// frame, after the post effects, and is gone after that. Positions are normalized screen coordinates like renderables
+pub struct DebugDraw {
+    pipeline: wgpu::RenderPipeline,
+    aspect: f32, // Width over height, keeps circles round
+    vertices: Vec<DebugVertex>, // Pairs, drawn as a line list
+    texts: Vec<RenderableText>,
+}
+
+impl DebugDraw {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, aspect: f32) -> Self {
+        let vs_module = device.create_shader_module(&wgpu::include_spirv!("debug.vert.spv"));
+        let fs_module = device.create_shader_module(&wgpu::include_spirv!("debug.frag.spv"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("debug_pipeline_layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("debug_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vs_module,
+                entry_point: "main",
+                buffers: &[
+                    wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<DebugVertex>() as wgpu::BufferAddress,
+                        step_mode: wgpu::InputStepMode::Vertex,
+                        attributes: &[
+                            wgpu::VertexAttribute {
+                                offset: 0,
+                                shader_location: 0,
+                                format: wgpu::VertexFormat::Float32x2,
+                            },
+                            wgpu::VertexAttribute {
+                                offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                                shader_location: 1,
+                                format: wgpu::VertexFormat::Float32x4,
+                            },
+                        ],
+                    },
+                ],
+            },
+            depth_stencil: None,
+            fragment: Some(wgpu::FragmentState {
+                module: &fs_module,
+                entry_point: "main",
+                targets: &[
+                    wgpu::ColorTargetState {
+                        format,
+                        write_mask: wgpu::ColorWrite::ALL,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    }
+                ]
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+        });
+
+        Self {
+            pipeline,
+            aspect,
+            vertices: Vec::new(),
+            texts: Vec::new(),
+        }
+    }
+
+    pub fn line(&mut self, from: [f32; 2], to: [f32; 2], color: [f32; 4]) {
+        self.vertices.push(DebugVertex { position: from, color });
+        self.vertices.push(DebugVertex { position: to, color });
+    }
+
+    // Outline from the top left corner p1 to the bottom right corner p2
+    pub fn rect(&mut self, p1: [f32; 2], p2: [f32; 2], color: [f32; 4]) {
+        self.line([p1[0], p1[1]], [p2[0], p1[1]], color);
+        self.line([p2[0], p1[1]], [p2[0], p2[1]], color);
+        self.line([p2[0], p2[1]], [p1[0], p2[1]], color);
+        self.line([p1[0], p2[1]], [p1[0], p1[1]], color);
+    }
+
+    // Radius is a fraction of the screen height, like light radii
+    pub fn circle(&mut self, center: [f32; 2], radius: f32, color: [f32; 4]) {
+        let aspect = self.aspect;
+        let point = |i: usize| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::PI * 2.;
+            [center[0] + angle.cos() * radius / aspect, center[1] + angle.sin() * radius]
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            let (from, to) = (point(i), point(i + 1));
+            self.line(from, to, color);
+        }
+    }
+
+    // Small outlined text with its top left corner at position, in the built in font
+    pub fn text(&mut self, position: [f32; 2], text: &str, color: [f32; 4]) {
+        self.texts.push(RenderableText {
+            spans: vec![TextSpan { text: text.to_owned(), size: 8., color, ..Default::default() }],
+            x: position[0],
+            y: position[1],
+            outline: Some(TextOutline { color: [0., 0., 0., color[3]], thickness: 1. }),
+            ..Default::default()
+        });
+    }
+
+    // The renderer draws these along with the rest of the text
+    pub fn take_texts(&mut self) -> Vec<RenderableText> {
+        std::mem::take(&mut self.texts)
+    }
+
+    pub fn draw(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, stats: &mut RenderStats) {
+        if self.vertices.is_empty() {
+            return;
+        }
+        stats.render_passes += 1;
+        stats.pipeline_switches += 1;
+        stats.buffer_allocations += 1;
+        stats.draw_calls += 1;
+        stats.vertices += self.vertices.len() as u32;
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("debug_vertices"),
+            contents: bytemuck::cast_slice(&self.vertices),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("debug_pass"),
+            color_attachments: &[
+                wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }
+                }
+            ],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.draw(0..self.vertices.len() as u32, 0..1);
+    }
+
+    // Called once the frame is out, shapes only last a single frame
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.texts.clear();
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Frame times kept around for the averages, a couple of seconds worth
+const HISTORY_LENGTH: usize = 120;
+// The OS oversleeps, so the last stretch before a frame is due gets spun away instead
+const SPIN_MARGIN: Duration = Duration::from_millis(1);
+
+// All times in milliseconds, over the last HISTORY_LENGTH frames unless said otherwise
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameStats {
+    pub frame_time: f32, // Start of the previous frame to the start of this one
+    pub average_frame_time: f32,
+    pub worst_frame_time: f32,
+    pub fps: f32, // From the average frame time
+    pub sleep_time: f32, // How long the limiter held the last frame back
+    pub render_time: f32, // CPU side of the last Renderer::render
+    pub frame_count: u64, // Since the renderer was created
+}
+
+// Holds frames back to the target FPS and keeps track of how evenly they come
+pub struct FramePacer {
+    target_fps: Option<u32>,
+    last_frame: Instant,
+    history: VecDeque<f32>,
+    stats: FrameStats,
+}
+
+impl FramePacer {
+    pub fn new(target_fps: Option<u32>) -> Self {
+        Self {
+            target_fps,
+            last_frame: Instant::now(),
+            history: VecDeque::with_capacity(HISTORY_LENGTH),
+            stats: FrameStats::default(),
+        }
+    }
+
+    pub fn set_target_fps(&mut self, target_fps: Option<u32>) {
+        self.target_fps = target_fps;
+    }
+
+    // Sleeps until the next frame is due, returns how long it's been since the previous one started
+    pub fn begin_frame(&mut self) -> Duration {
+        let sleep_start = Instant::now();
+        if let Some(target_fps) = self.target_fps.filter(|target_fps| *target_fps > 0) {
+            let frame_budget = Duration::from_secs_f64(1. / target_fps as f64);
+            let due = self.last_frame + frame_budget;
+            let now = Instant::now();
+            if due > now + SPIN_MARGIN {
+                std::thread::sleep(due - now - SPIN_MARGIN);
+            }
+            while Instant::now() < due {
+                std::thread::yield_now();
+            }
+        }
+
+        let now = Instant::now();
+        let frame_time = now - self.last_frame;
+        self.last_frame = now;
+
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(frame_time.as_secs_f32() * 1000.);
+        let average_frame_time = self.history.iter().sum::<f32>() / self.history.len() as f32;
+        self.stats = FrameStats {
+            frame_time: frame_time.as_secs_f32() * 1000.,
+            average_frame_time,
+            worst_frame_time: self.history.iter().cloned().fold(0., f32::max),
+            fps: if average_frame_time > 0. { 1000. / average_frame_time } else { 0. },
+            sleep_time: (now - sleep_start).as_secs_f32() * 1000.,
+            render_time: self.stats.render_time,
+            frame_count: self.stats.frame_count + 1,
+        };
+        frame_time
+    }
+
+    pub fn record_render_time(&mut self, render_time: Duration) {
+        self.stats.render_time = render_time.as_secs_f32() * 1000.;
+    }
+
+    pub fn stats(&self) -> &FrameStats {
+        &self.stats
+    }
+}
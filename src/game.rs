@@ -10,30 +10,125 @@ use crate::systems::player_animation::*;
 use crate::systems::player_pineapple::*;
 use crate::systems::points_ticking_down::*;
 use crate::systems::flag_reached::*;
+use crate::systems::particles::*;
+use crate::systems::camera::*;
+use crate::systems::spin::*;
+use crate::ui::*;
+use crate::stats::Stats;
+use crate::spatial_hash::SpatialHash;
+use crate::random::random;
 use std::any;
 use std::any::Any;
 use std::cell::{RefCell, RefMut};
 use std::collections::HashMap;
+use std::time::Duration;
+
+// Falling faster than this before touching the ground kicks up dust
+const LANDING_DUST_MIN_VELOCITY: f32 = 0.5;
+// How often the benchmark scene prints how it's doing
+const BENCHMARK_REPORT_FRAMES: u64 = 120;
+// Bodies falling through the physics benchmark's tiles, the tile count is up to whoever runs it
+const PHYSICS_BENCHMARK_BODIES: usize = 500;
+// Tile counts the physics benchmark goes through when it's not given any, doubling so the growth is easy to read
+const PHYSICS_BENCHMARK_TILE_COUNTS: [usize; 5] = [1_000, 2_000, 4_000, 8_000, 16_000];
+// Frames each tile count gets before the benchmark moves on to the next
+const PHYSICS_BENCHMARK_FRAMES: u64 = 600;
+// Simulation steps are this long, 120 of them a second
+const FIXED_TIMESTEP_MS: f32 = 1000. / 120.;
+// Steps a single frame may catch up on, so a slow frame doesn't lead to an even slower one
+const MAX_STEPS_PER_FRAME: u32 = 8;
 
 enum Scenes {
     Ingame,
     GameOver,
     YouWon,
+    Benchmark, // Lots of spinning sprites and nothing else, for seeing how far the renderer goes
+    PhysicsBenchmark, // Thousands of static tiles with bodies falling through them, for the broadphase
+}
+
+// Where the physics benchmark is in its series of tile counts and what it measured so far
+struct PhysicsBenchmark {
+    tile_counts: Vec<usize>,
+    results: Vec<(usize, [f32; 3])>, // Tile count and milliseconds per step, one for every tile count done
+    frames: u64, // Of the current tile count
+    steps: u32,
+    times: [f32; 3], // Broadphase, physics and collision milliseconds of the current tile count, all steps added up
+}
+
+impl PhysicsBenchmark {
+    fn new(tile_counts: Vec<usize>) -> Self {
+        Self {tile_counts, results: Vec::new(), frames: 0, steps: 0, times: [0.; 3]}
+    }
+
+    fn tile_count(&self) -> usize {
+        self.tile_counts[self.results.len()]
+    }
+
+    fn record_frame(&mut self, steps: u32, times: [f32; 3]) {
+        self.frames += 1;
+        self.steps += steps;
+        for (total, time) in self.times.iter_mut().zip(times.iter()) {
+            *total += time;
+        }
+    }
+
+    // Stores the averages of the current tile count and returns the next one, None once they're all done
+    fn next_tile_count(&mut self) -> Option<usize> {
+        let steps = self.steps.max(1) as f32;
+        let per_step = [self.times[0] / steps, self.times[1] / steps, self.times[2] / steps];
+        println!(
+            "Physics benchmark: {} tiles, {:.3} ms broadphase, {:.3} ms physics, {:.3} ms collision per step",
+            self.tile_count(), per_step[0], per_step[1], per_step[2],
+        );
+        self.results.push((self.tile_count(), per_step));
+        self.frames = 0;
+        self.steps = 0;
+        self.times = [0.; 3];
+        self.tile_counts.get(self.results.len()).copied()
+    }
+
+    // Physics and collision staying about the same as the tile count doubles is the broadphase doing its job, only
+    // rebuilding it has to go through every tile. Checking every body against every tile would double them along with the tiles
+    fn print_results(&self) {
+        println!("Physics benchmark, {} bodies, milliseconds per simulation step:", PHYSICS_BENCHMARK_BODIES);
+        println!("{:>8} {:>11} {:>9} {:>10} {:>9} {:>13}", "tiles", "broadphase", "physics", "collision", "total", "vs the first");
+        let first_total: f32 = self.results.first().map(|(_, times)| times.iter().sum()).unwrap_or(0.);
+        for (tile_count, times) in self.results.iter() {
+            let total: f32 = times.iter().sum();
+            println!(
+                "{:>8} {:>11.3} {:>9.3} {:>10.3} {:>9.3} {:>12.2}x",
+                tile_count, times[0], times[1], times[2], total, total / first_total.max(f32::EPSILON),
+            );
+        }
+    }
 }
 
 pub struct Game {
     target_resolution: [u32; 2],
     keyboard_input_queue: Vec<winit::event::KeyboardInput>,
+    step_input: Vec<winit::event::KeyboardInput>, // Left over by the UI, for the next simulation step
+    step_accumulator: f32, // Milliseconds not simulated yet
+    step_alpha: f32, // step_accumulator as a fraction of a step
+    previous_positions: Vec<Option<Position>>, // Before the last step
+    spatial_hash: SpatialHash, // Rigid bodies, rebuilt every step
+    physics_benchmark: Option<PhysicsBenchmark>, // Only while the physics benchmark runs, kept across its scene swaps
     player_index: Option<usize>,
     entity_count: usize,
     component_vectors: Vec<Box<dyn ComponentsVector>>, // Vector containing other vectors - each vector here is of a component type and has components of that type;
-    current_scene: Scenes
+    current_scene: Scenes,
+    particle_texture_id: usize,
+    hud_font: FontId,
+    stats: Stats, // Timings of the current frame, renderer numbers of the previous one
+    ui: Ui,
+    paused: bool,
+    camera: Camera,
+    level_size: [f32; 2], // Normalized, the camera never shows anything outside of it
 }
 
 impl Game {
     pub fn new(target_resolution: [u32; 2]) -> Self {
 
-        Self {target_resolution, entity_count: 0, component_vectors: Vec::new(), player_index: None, keyboard_input_queue: Vec::new(), current_scene: Scenes::Ingame}
+        Self {target_resolution, entity_count: 0, component_vectors: Vec::new(), player_index: None, keyboard_input_queue: Vec::new(), current_scene: Scenes::Ingame, particle_texture_id: 0, hud_font: FontId(0), stats: Stats::default(), ui: Ui::default(), paused: false, camera: Camera {x: 0., y: 0.}, level_size: [1., 1.], step_input: Vec::new(), step_accumulator: 0., step_alpha: 0., previous_positions: Vec::new(), spatial_hash: SpatialHash::default(), physics_benchmark: None}
     }
 
     fn clear_scene(&mut self) {
@@ -41,9 +136,130 @@ impl Game {
         self.entity_count = 0;
         self.player_index = None;
         self.keyboard_input_queue = Vec::new();
+        self.step_input = Vec::new();
+        self.step_accumulator = 0.;
+        self.step_alpha = 0.;
+        self.previous_positions = Vec::new();
+        self.camera = Camera {x: 0., y: 0.};
+        self.level_size = [1., 1.];
+        self.ui = Ui::default();
+        self.paused = false;
     }
 
     fn init_scene_in_game(&mut self, renderer: &mut Renderer) {
+        // Hand drawn 5x7 font for the HUD, sizes in multiples of 8
+        self.hud_font = renderer.register_bitmap_font("res/font/pixel5x7.fnt").unwrap_or_else(|error| {
+            println!("{}, falling back to the built in font", error);
+            FontId(0)
+        });
+        let panel_texture = or_missing(renderer.register_texture("res/ui/panel.png"), renderer);
+        let player_icon = or_missing(renderer.register_texture("res/sillyboi.png"), renderer);
+        self.ui = Ui::new(vec![
+            // Points and how long until the next one is lost
+            anchored([0., 0.], [0.01, 0.01], Widget {
+                background: Some(panel_background(panel_texture, [1., 1., 1., 1.])),
+                padding: [0.012, 0.02],
+                ..Widget::new(WidgetKind::Row {
+                    spacing: 0.01,
+                    align: VerticalAlign::Center,
+                    children: vec![
+                        Widget::new(WidgetKind::Image {
+                            texture_id: player_icon,
+                            size: [32. / self.target_resolution[0] as f32, 32. / self.target_resolution[1] as f32],
+                            tint: [1., 1., 1., 1.],
+                        }),
+                        Widget::new(WidgetKind::Column {
+                            spacing: 0.01,
+                            align: HorizontalAlign::Left,
+                            children: vec![
+                                Widget {
+                                    id: "points".to_owned(),
+                                    ..Widget::new(WidgetKind::Label {
+                                        spans: vec![
+                                            TextSpan {text: "Points: ".to_owned(), font: self.hud_font, color: [1., 1., 1., 1.], size: 24., ..Default::default()},
+                                            TextSpan {text: String::new(), font: self.hud_font, color: [1., 0., 0., 1.], size: 24., ..Default::default()},
+                                        ],
+                                        outline: Some(TextOutline {color: [0., 0., 0., 1.], thickness: 3.}),
+                                        shadow: None,
+                                    })
+                                },
+                                Widget {
+                                    id: "point_timer".to_owned(),
+                                    ..Widget::new(WidgetKind::ProgressBar {
+                                        value: 1.,
+                                        size: [0.16, 0.012],
+                                        texture_id: renderer.white_texture(),
+                                        fill_color: [1., 0.85, 0.2, 1.],
+                                        empty_color: [0., 0., 0., 0.5],
+                                    })
+                                },
+                            ],
+                        }),
+                    ],
+                })
+            }),
+            // Hidden until P gets pressed
+            Widget {
+                id: "pause_menu".to_owned(),
+                visible: false,
+                modal: true,
+                ..anchored([0.5, 0.5], [0., 0.], menu_panel(panel_texture, vec![
+                    title("Paused", None),
+                    menu_button(panel_texture, "resume", "Resume", self.hud_font),
+                    menu_button(panel_texture, "restart", "Restart", self.hud_font),
+                    menu_button(panel_texture, "quit", "Quit", self.hud_font),
+                ]))
+            },
+        ]);
+
+        // Load background
+        {
+            renderer.set_clear_color([0.27, 0.51, 0.78, 1.]);
+
+            // Late afternoon, dim enough for the lights to show
+            let ambient_light_index = self.add_entity();
+            self.add_component_to_entity(ambient_light_index, AmbientLight {color: [0.8, 0.8, 1.], intensity: 0.75});
+
+            let sky_texture = or_missing(renderer.register_tiling_texture("res/background/sky.png"), renderer);
+            let sky_index = self.add_entity();
+            self.add_component_to_entity(sky_index, ParallaxLayer {
+                texture_id: sky_texture,
+                scroll_factor: [0., 0.],
+                tile_size: [8. / self.target_resolution[0] as f32, 1.],
+                repeat_x: true,
+                repeat_y: false,
+                vertical_anchor: VerticalAnchor::Top,
+                offset: [0., 0.],
+                z: 0,
+            });
+
+            let mountains_texture = or_missing(renderer.register_tiling_texture("res/background/mountains.png"), renderer);
+            let mountains_index = self.add_entity();
+            self.add_component_to_entity(mountains_index, ParallaxLayer {
+                texture_id: mountains_texture,
+                scroll_factor: [0.2, 0.1],
+                tile_size: [640. / self.target_resolution[0] as f32, 240. / self.target_resolution[1] as f32],
+                repeat_x: true,
+                repeat_y: false,
+                vertical_anchor: VerticalAnchor::Bottom,
+                offset: [0., -120. / self.target_resolution[1] as f32],
+                z: 1,
+            });
+
+            let hills_texture = or_missing(renderer.register_tiling_texture("res/background/hills.png"), renderer);
+            let hills_index = self.add_entity();
+            self.add_component_to_entity(hills_index, ParallaxLayer {
+                texture_id: hills_texture,
+                scroll_factor: [0.5, 0.3],
+                tile_size: [512. / self.target_resolution[0] as f32, 160. / self.target_resolution[1] as f32],
+                repeat_x: true,
+                repeat_y: false,
+                vertical_anchor: VerticalAnchor::Bottom,
+                offset: [0., -100. / self.target_resolution[1] as f32],
+                z: 2,
+            });
+        }
+
         // Load player
         {   
             // Add a new entity for the player
@@ -56,12 +272,12 @@ impl Game {
 
                 // Idle animation
                 {
-                    let player_texture = renderer.register_texture("res/sillyboi.png");
-                    let player_texture_2 = renderer.register_texture("res/sillyboi2.png");
+                    let player_texture = or_missing(renderer.register_texture("res/sillyboi.png"), renderer);
+                    let player_texture_2 = or_missing(renderer.register_texture("res/sillyboi2.png"), renderer);
                     let anim_idle = Animation {
                         animation_name: "idle", 
                         time_per_frame_ms: 250,
-                        time_since_last_frame: 0,
+                        time_since_last_frame: 0.,
                         current_frame_index: 1,
                         running: true,
                         sprites: vec![
@@ -71,6 +287,7 @@ impl Game {
                                 width_normalized: 64. / self.target_resolution[0] as f32,
                                 height_normalized: 64. / self.target_resolution[1] as f32,
                                 z: 10,
+                                ..Default::default()
                             },
                             Sprite {
                                 texture_id: player_texture_2,
@@ -78,6 +295,7 @@ impl Game {
                                 width_normalized: 64. / self.target_resolution[0] as f32,
                                 height_normalized: 64. / self.target_resolution[1] as f32,
                                 z: 10,
+                                ..Default::default()
                             },
                         ]
                     };
@@ -92,13 +310,14 @@ impl Game {
                         let filename_prefix: String = "res/sillyboi_running_right/row-1-col-".to_owned();
                         let extension = ".png";
                         let full_filename = filename_prefix + &(i + 1).to_string() + extension;
-                        let texture = renderer.register_texture(&full_filename);
+                        let texture = or_missing(renderer.register_texture(&full_filename), renderer);
                         sprites.push(Sprite {
                             texture_id: texture,
                             render: true,
                             width_normalized: 64. / self.target_resolution[0] as f32,
                             height_normalized: 64. / self.target_resolution[1] as f32,
                             z: 10,
+                            ..Default::default()
                         });
                     }   
                     let anim = Animation {
@@ -107,7 +326,7 @@ impl Game {
                         running: true,
                         sprites,
                         time_per_frame_ms: 50,
-                        time_since_last_frame: 0,
+                        time_since_last_frame: 0.,
                     };
                     anim_map.current_animation_name = anim.animation_name;
                     anim_map.map.insert(anim.animation_name, anim);
@@ -120,13 +339,14 @@ impl Game {
                         let filename_prefix: String = "res/sillyboi_running_left/row-1-col-".to_owned();
                         let extension = ".png";
                         let full_filename = filename_prefix + &(i + 1).to_string() + extension;
-                        let texture = renderer.register_texture(&full_filename);
+                        let texture = or_missing(renderer.register_texture(&full_filename), renderer);
                         sprites.push(Sprite {
                             texture_id: texture,
                             render: true,
                             width_normalized: 64. / self.target_resolution[0] as f32,
                             height_normalized: 64. / self.target_resolution[1] as f32,
                             z: 10,
+                            ..Default::default()
                         });
                     }   
                     let anim = Animation {
@@ -135,7 +355,7 @@ impl Game {
                         running: true,
                         sprites,
                         time_per_frame_ms: 50,
-                        time_since_last_frame: 0,
+                        time_since_last_frame: 0.,
                     };
                     anim_map.current_animation_name = anim.animation_name;
                     anim_map.map.insert(anim.animation_name, anim);
@@ -144,13 +364,14 @@ impl Game {
                 // Jump animation
                 {
                     let mut sprites: Vec<Sprite> = Vec::new();
-                    let texture = renderer.register_texture("res/sillyboi_jump/Jump (32x32).png");
+                    let texture = or_missing(renderer.register_texture("res/sillyboi_jump/Jump (32x32).png"), renderer);
                     sprites.push(Sprite {
                         texture_id: texture,
                         render: true,
                         width_normalized: 64. / self.target_resolution[0] as f32,
                         height_normalized: 64. / self.target_resolution[1] as f32,
                         z: 10,
+                        ..Default::default()
                     });
                     let anim = Animation {
                         animation_name: "jump",
@@ -158,7 +379,7 @@ impl Game {
                         running: true,
                         sprites,
                         time_per_frame_ms: 50,
-                        time_since_last_frame: 0,
+                        time_since_last_frame: 0.,
                     };
                     anim_map.current_animation_name = anim.animation_name;
                     anim_map.map.insert(anim.animation_name, anim);
@@ -167,13 +388,14 @@ impl Game {
                 // Fall animation
                 {
                     let mut sprites: Vec<Sprite> = Vec::new();
-                    let texture = renderer.register_texture("res/sillyboi_fall/Fall (32x32).png");
+                    let texture = or_missing(renderer.register_texture("res/sillyboi_fall/Fall (32x32).png"), renderer);
                     sprites.push(Sprite {
                         texture_id: texture,
                         render: true,
                         width_normalized: 64. / self.target_resolution[0] as f32,
                         height_normalized: 64. / self.target_resolution[1] as f32,
                         z: 10,
+                        ..Default::default()
                     });
                     let anim = Animation {
                         animation_name: "fall",
@@ -181,7 +403,7 @@ impl Game {
                         running: true,
                         sprites,
                         time_per_frame_ms: 50,
-                        time_since_last_frame: 0,
+                        time_since_last_frame: 0.,
                     };
                     anim_map.current_animation_name = anim.animation_name;
                     anim_map.map.insert(anim.animation_name, anim);
@@ -200,13 +422,42 @@ impl Game {
             self.add_component_to_entity(player_index, CollisionList {list: Vec::new()});
             self.add_component_to_entity(player_index, PlayerState{state: PlayerStateKind::Idle});
             self.add_component_to_entity(player_index, EntityType::Player);
-            self.add_component_to_entity(player_index, Points{points: 10, time_since_last_point_change_ms: 0});
+            self.add_component_to_entity(player_index, Points{points: 10, time_since_last_point_change_ms: 0.});
+            self.add_component_to_entity(player_index, PointLight {
+                color: [1., 0.8, 0.55],
+                intensity: 0.9,
+                radius: 0.45,
+                height: 0.1,
+                offset: [32. / self.target_resolution[0] as f32, 32. / self.target_resolution[1] as f32],
+            });
+
+            // Dust when landing, only ever bursts
+            // Gets drawn at all kinds of sizes, smooth looks better than blocky here
+            self.particle_texture_id = or_missing(renderer.register_texture_with_options("res/particle.png", &TextureOptions {filter: wgpu::FilterMode::Linear, ..Default::default()}), renderer);
+            self.add_component_to_entity(player_index, ParticleEmitter {
+                texture_id: self.particle_texture_id,
+                emitting: false,
+                offset: [32. / self.target_resolution[0] as f32, 64. / self.target_resolution[1] as f32],
+                spawn_area: [40. / self.target_resolution[0] as f32, 0.],
+                lifetime_ms: [300, 500],
+                vel_x: [-0.15, 0.15],
+                vel_y: [-0.15, -0.05],
+                gravity: [0.3, 0.3],
+                start_color: [0.8, 0.75, 0.7, 0.8],
+                end_color: [0.8, 0.75, 0.7, 0.],
+                start_size: [6. / self.target_resolution[0] as f32, 6. / self.target_resolution[1] as f32],
+                end_size: [12. / self.target_resolution[0] as f32, 12. / self.target_resolution[1] as f32],
+                z: 11,
+                ..Default::default()
+            });
         }
 
         // Load terrain
         {   
-            let terrain_texture_index = renderer.register_texture("res/platformthing.png");
+            let terrain_texture_index = or_missing(renderer.register_texture("res/platformthing.png"), renderer);
+            let terrain_normal_map = or_missing(renderer.register_normal_map("res/platformthing_normal.png"), renderer);
             let max_squares = self.target_resolution[0] / 96 + 1;
+            self.level_size = [(max_squares * 96) as f32 / self.target_resolution[0] as f32, 1.];
             for square_n in 0..max_squares as usize {
                 let offset: f32 = 96. / self.target_resolution[0] as f32 * square_n as f32;
                 let terrain_index = self.add_entity();
@@ -216,6 +467,8 @@ impl Game {
                     width_normalized: 96. / self.target_resolution[0] as f32,
                     height_normalized: 96. / self.target_resolution[1] as f32,
                     z: 1,
+                    normal_map_id: Some(terrain_normal_map),
+                    ..Default::default()
                 });
                 self.add_component_to_entity(terrain_index, Position {x: offset, y: 600. / self.target_resolution[1] as f32});
                 self.add_component_to_entity(terrain_index, RigidBody {width: 96. / self.target_resolution[0] as f32, height: 96. / self.target_resolution[1] as f32});
@@ -231,6 +484,8 @@ impl Game {
                     width_normalized: 96. / self.target_resolution[0] as f32,
                     height_normalized: 96. / self.target_resolution[1] as f32,
                     z: 1,
+                    normal_map_id: Some(terrain_normal_map),
+                    ..Default::default()
                 });
                 self.add_component_to_entity(terrain_index, Position {x: (self.target_resolution[0] - 96) as f32 / self.target_resolution[0] as f32, y: 504. / self.target_resolution[1] as f32});
                 self.add_component_to_entity(terrain_index, RigidBody {width: 96. / self.target_resolution[0] as f32, height: 96. / self.target_resolution[1] as f32});
@@ -245,6 +500,8 @@ impl Game {
                     width_normalized: 96. / self.target_resolution[0] as f32,
                     height_normalized: 96. / self.target_resolution[1] as f32,
                     z: 1,
+                    normal_map_id: Some(terrain_normal_map),
+                    ..Default::default()
                 });
                 self.add_component_to_entity(terrain_index, Position {x: 0. / self.target_resolution[0] as f32, y: 504. / self.target_resolution[1] as f32});
                 self.add_component_to_entity(terrain_index, RigidBody {width: 96. / self.target_resolution[0] as f32, height: 96. / self.target_resolution[1] as f32});
@@ -255,18 +512,23 @@ impl Game {
                 for i in 0..10 {
                     let pineapple_index = self.add_entity();
                     let mut sprites: Vec<Sprite> = Vec::new();
+                    // Outlined so they stand out from the background
+                    let outline_material = renderer.get_material_id("outline");
                     // TODO: Load animation only once and reuse it for each pineapple
                     for i in 0..17 {
                         let filename_prefix: String = "res/pineapple/row-1-col-".to_owned();
                         let extension = ".png";
                         let full_filename = filename_prefix + &(i + 1).to_string() + extension;
-                        let texture = renderer.register_texture(&full_filename);
+                        let texture = or_missing(renderer.register_texture(&full_filename), renderer);
                         sprites.push(Sprite {
                             texture_id: texture,
                             render: true,
                             width_normalized: 64. / self.target_resolution[0] as f32,
                             height_normalized: 64. / self.target_resolution[1] as f32,
                             z: 1,
+                            material_id: outline_material,
+                            material_params: [1., 0., 0., 0.],
+                            ..Default::default()
                         });
                     }  
                     self.add_component_to_entity(pineapple_index, Animation {
@@ -274,7 +536,7 @@ impl Game {
                         current_frame_index: 0,
                         running: true,
                         time_per_frame_ms: 50,
-                        time_since_last_frame: 0,
+                        time_since_last_frame: 0.,
                         sprites
                     });
                     let pineapple_x = 200 + i * 100;
@@ -299,13 +561,14 @@ impl Game {
                         let filename_prefix: String = "res/end_flag/row-1-col-".to_owned();
                         let extension = ".png";
                         let full_filename = filename_prefix + &(i + 1).to_string() + extension;
-                        let texture = renderer.register_texture(&full_filename);
+                        let texture = or_missing(renderer.register_texture(&full_filename), renderer);
                         sprites.push(Sprite {
                             texture_id: texture,
                             render: true,
                             width_normalized: 128. / self.target_resolution[0] as f32,
                             height_normalized: 128. / self.target_resolution[1] as f32,
                             z: 0,
+                            ..Default::default()
                         });
                     }   
                     Animation {
@@ -314,24 +577,162 @@ impl Game {
                         running: true,
                         sprites,
                         time_per_frame_ms: 50,
-                        time_since_last_frame: 0,
+                        time_since_last_frame: 0.,
                     }
                 };
 
                 self.add_component_to_entity(flag_id, anim);
+                self.add_component_to_entity(flag_id, PointLight {
+                    color: [1., 0.85, 0.3],
+                    intensity: 0.7,
+                    radius: 0.35,
+                    height: 0.15,
+                    offset: [64. / self.target_resolution[0] as f32, 32. / self.target_resolution[1] as f32],
+                });
+
+                // Confetti
+                self.add_component_to_entity(flag_id, ParticleEmitter {
+                    texture_id: self.particle_texture_id,
+                    spawn_rate: 20.,
+                    offset: [64. / self.target_resolution[0] as f32, 16. / self.target_resolution[1] as f32],
+                    spawn_area: [64. / self.target_resolution[0] as f32, 0.],
+                    lifetime_ms: [800, 1400],
+                    vel_x: [-0.2, 0.2],
+                    vel_y: [-0.6, -0.3],
+                    gravity: [0.8, 1.2],
+                    start_color: [1., 0.85, 0.2, 1.],
+                    end_color: [1., 0.3, 0.6, 0.],
+                    start_size: [6. / self.target_resolution[0] as f32, 6. / self.target_resolution[1] as f32],
+                    end_size: [4. / self.target_resolution[0] as f32, 4. / self.target_resolution[1] as f32],
+                    z: 2,
+                    ..Default::default()
+                });
             }
         }
     }
-    fn init_scene_game_over(&mut self, renderer: &mut Renderer) {}
+    fn init_scene_game_over(&mut self, renderer: &mut Renderer) {
+        let panel_texture = or_missing(renderer.register_texture("res/ui/panel.png"), renderer);
+        self.ui = Ui::new(vec![
+            anchored([0.5, 0.5], [0., 0.], menu_panel(panel_texture, vec![
+                title("Game Over", None),
+                menu_button(panel_texture, "restart", "Try again", self.hud_font),
+                menu_button(panel_texture, "quit", "Quit", self.hud_font),
+            ])),
+        ]);
+    }
     fn init_scene_you_won(&mut self, renderer: &mut Renderer, args: Option<Vec<Box<dyn Any>>>) {
+        let mut final_points: Option<u32> = None;
         if let Some(args) = args {
             if let Some(player_points) = args.get(0) {
                 if let Some(points) = player_points.as_ref().downcast_ref::<u32>() {
                     let entity_id = self.add_entity();
-                    self.add_component_to_entity(entity_id, Points { points: *points, time_since_last_point_change_ms: 0 });
+                    self.add_component_to_entity(entity_id, Points { points: *points, time_since_last_point_change_ms: 0. });
+                    final_points = Some(*points);
                 }
             }
         }
+
+        let panel_texture = or_missing(renderer.register_texture("res/ui/panel.png"), renderer);
+        let mut children = vec![title("You won!", Some(TextShadow {color: [0.8, 0.3, 0.3, 1.], offset: [4., 4.]}))];
+        if let Some(points) = final_points {
+            children.push(Widget::new(WidgetKind::Label {
+                spans: vec![
+                    TextSpan {text: "Points: ".to_owned(), color: [1., 0.5, 0.5, 1.], size: 32., ..Default::default()},
+                    TextSpan {text: points.to_string(), color: [1., 0.85, 0.2, 1.], size: 32., ..Default::default()},
+                ],
+                outline: None,
+                shadow: None,
+            }));
+        }
+        children.push(menu_button(panel_texture, "restart", "Play again", self.hud_font));
+        children.push(menu_button(panel_texture, "quit", "Quit", self.hud_font));
+        self.ui = Ui::new(vec![anchored([0.5, 0.5], [0., 0.], menu_panel(panel_texture, children))]);
+    }
+
+    fn init_scene_benchmark(&mut self, renderer: &mut Renderer, args: Option<Vec<Box<dyn Any>>>) {
+        let sprite_count = args.as_ref()
+            .and_then(|args| args.get(0))
+            .and_then(|sprite_count| sprite_count.as_ref().downcast_ref::<usize>())
+            .copied()
+            .unwrap_or(50_000);
+        let textures = [
+            or_missing(renderer.register_texture("res/sillyboi.png"), renderer),
+            or_missing(renderer.register_texture("res/pineapple/Pineapple.png"), renderer),
+            or_missing(renderer.register_texture("res/particle.png"), renderer),
+        ];
+        renderer.set_clear_color([0.1, 0.1, 0.15, 1.]);
+
+        // Same seed every time so runs can be compared
+        let mut rng_state: u32 = 0x2545_f491;
+        for i in 0..sprite_count {
+            let size = (8. + random(&mut rng_state) * 24.) / self.target_resolution[1] as f32;
+            let entity_id = self.add_entity();
+            self.add_component_to_entity(entity_id, Position {x: random(&mut rng_state) - size / 2., y: random(&mut rng_state) - size / 2.});
+            // A z per texture keeps every texture's sprites next to each other in the draw order, so they batch
+            self.add_component_to_entity(entity_id, Sprite {
+                texture_id: textures[i % textures.len()],
+                z: (i % textures.len()) as u32,
+                width_normalized: size * self.target_resolution[1] as f32 / self.target_resolution[0] as f32,
+                height_normalized: size,
+                tint: [0.5 + random(&mut rng_state) * 0.5, 0.5 + random(&mut rng_state) * 0.5, 0.5 + random(&mut rng_state) * 0.5, 1.],
+                rotation: random(&mut rng_state) * std::f32::consts::TAU,
+                ..Default::default()
+            });
+            self.add_component_to_entity(entity_id, Spin {speed: (random(&mut rng_state) - 0.5) * 4.});
+        }
+        println!("Benchmark: {} sprites", sprite_count);
+    }
+
+    fn init_scene_physics_benchmark(&mut self, renderer: &mut Renderer, args: Option<Vec<Box<dyn Any>>>) {
+        let tile_count = args.as_ref()
+            .and_then(|args| args.get(0))
+            .and_then(|tile_count| tile_count.as_ref().downcast_ref::<usize>())
+            .copied()
+            .unwrap_or(5_000);
+        renderer.set_clear_color([0.1, 0.1, 0.15, 1.]);
+        let pixel = [1. / self.target_resolution[0] as f32, 1. / self.target_resolution[1] as f32];
+
+        // Rows of 8x4 tiles with 8 pixel gaps, every other row shifted so whatever falls through a gap lands on the row below
+        let tiles_per_row = self.target_resolution[0] as usize / 16;
+        let row_count = (tile_count + tiles_per_row - 1) / tiles_per_row;
+        let row_spacing = 0.8 / row_count.max(1) as f32;
+        for i in 0..tile_count {
+            let (row, column) = (i / tiles_per_row, i % tiles_per_row);
+            let entity_id = self.add_entity();
+            self.add_component_to_entity(entity_id, Position {
+                x: (column * 16 + (row % 2) * 8) as f32 * pixel[0],
+                y: 0.2 + row as f32 * row_spacing,
+            });
+            self.add_component_to_entity(entity_id, RigidBody {width: 8. * pixel[0], height: 4. * pixel[1]});
+            self.add_component_to_entity(entity_id, BlocksMovement {blocks: true});
+            self.add_component_to_entity(entity_id, Sprite {
+                texture_id: renderer.white_texture(),
+                width_normalized: 8. * pixel[0],
+                height_normalized: 4. * pixel[1],
+                tint: [0.4, 0.4, 0.45, 1.],
+                ..Default::default()
+            });
+        }
+
+        // Same seed every time so runs can be compared
+        let mut rng_state: u32 = 0x2545_f491;
+        for _ in 0..PHYSICS_BENCHMARK_BODIES {
+            let entity_id = self.add_entity();
+            self.add_component_to_entity(entity_id, Position {x: random(&mut rng_state), y: random(&mut rng_state) * 0.15});
+            self.add_component_to_entity(entity_id, Velocity {vel_x: (random(&mut rng_state) - 0.5) * 0.4, vel_y: 0.});
+            self.add_component_to_entity(entity_id, Gravity {affected_by_gravity: true});
+            self.add_component_to_entity(entity_id, RigidBody {width: 4. * pixel[0], height: 4. * pixel[1]});
+            self.add_component_to_entity(entity_id, CollisionList {list: Vec::new()});
+            self.add_component_to_entity(entity_id, Sprite {
+                texture_id: renderer.white_texture(),
+                width_normalized: 4. * pixel[0],
+                height_normalized: 4. * pixel[1],
+                tint: [1., 0.8, 0.2, 1.],
+                z: 1,
+                ..Default::default()
+            });
+        }
+        println!("Physics benchmark: {} tiles, {} bodies", tile_count, PHYSICS_BENCHMARK_BODIES);
     }
 
     pub fn init(&mut self, renderer: &mut Renderer) {
@@ -339,18 +740,36 @@ impl Game {
         self.swap_scene(Scenes::Ingame, renderer, None);
     }
 
+    // Starts in the benchmark scene instead of the game
+    pub fn init_benchmark(&mut self, renderer: &mut Renderer, sprite_count: usize) {
+        let args: Vec<Box<dyn Any>> = vec![Box::new(sprite_count)];
+        self.swap_scene(Scenes::Benchmark, renderer, Some(args));
+    }
+
+    // Starts in the physics benchmark instead of the game. It runs every tile count in turn, prints how long the
+    // physics took per step for each and ends the game after the last one
+    pub fn init_physics_benchmark(&mut self, renderer: &mut Renderer, tile_counts: Vec<usize>) {
+        let tile_counts = if tile_counts.is_empty() { PHYSICS_BENCHMARK_TILE_COUNTS.to_vec() } else { tile_counts };
+        let args: Vec<Box<dyn Any>> = vec![Box::new(tile_counts[0])];
+        self.physics_benchmark = Some(PhysicsBenchmark::new(tile_counts));
+        self.swap_scene(Scenes::PhysicsBenchmark, renderer, Some(args));
+    }
+
     fn swap_scene(&mut self, scene: Scenes, renderer: &mut Renderer, args: Option<Vec<Box<dyn Any>>>) {
         self.clear_scene();
+        renderer.set_clear_color([0., 0., 0., 1.]);
 
         match scene {
             Scenes::Ingame => { self.init_scene_in_game(renderer); },
             Scenes::GameOver => { self.init_scene_game_over(renderer); },
             Scenes::YouWon => { self.init_scene_you_won(renderer, args); }
+            Scenes::Benchmark => { self.init_scene_benchmark(renderer, args); }
+            Scenes::PhysicsBenchmark => { self.init_scene_physics_benchmark(renderer, args); }
         }
         self.current_scene = scene;
     }
 
-    pub fn update(&mut self, time_passed: u128, renderer: &mut Renderer) -> bool {
+    pub fn update(&mut self, time_passed: Duration, renderer: &mut Renderer) -> bool {
         // LEFT AS AN EXAMPLE HERE ON HOW TO ITERATE AND SHIT
         // if false {
         //     let mut names = self.borrow_component_vector_mut::<Name>().unwrap();
@@ -360,22 +779,123 @@ impl Game {
         //     }
         // }
         
+        // The physics benchmark moves on once it has enough frames of the current tile count, and ends the game after the last
+        let next_tile_count = self.physics_benchmark.as_mut()
+            .filter(|physics_benchmark| physics_benchmark.frames >= PHYSICS_BENCHMARK_FRAMES)
+            .map(|physics_benchmark| physics_benchmark.next_tile_count());
+        match next_tile_count {
+            Some(Some(tile_count)) => {
+                let args: Vec<Box<dyn Any>> = vec![Box::new(tile_count)];
+                self.swap_scene(Scenes::PhysicsBenchmark, renderer, Some(args));
+            },
+            Some(None) => {
+                if let Some(physics_benchmark) = &self.physics_benchmark {
+                    physics_benchmark.print_results();
+                }
+                return true;
+            },
+            None => {},
+        }
+
+        let entity_count = self.live_entity_count();
+        self.stats.begin_frame(renderer.frame_stats(), renderer.render_stats(), entity_count);
+        if let Scenes::Benchmark = self.current_scene {
+            let frame_stats = renderer.frame_stats();
+            if frame_stats.frame_count % BENCHMARK_REPORT_FRAMES == 0 {
+                let render_stats = renderer.render_stats();
+                println!(
+                    "Benchmark: {:.0} fps, {:.2} ms avg, {:.2} ms worst, {:.2} ms render, {} sprites in {} draw calls",
+                    frame_stats.fps, frame_stats.average_frame_time, frame_stats.worst_frame_time, frame_stats.render_time, render_stats.instances, render_stats.draw_calls,
+                );
+            }
+        }
+
         let mut scene_swap_opt: Option<Scenes> = None;
         let mut scene_swap_args: Option<Vec<Box<dyn Any>>> = None;
-        
-        // Health system
+
+        // UI goes first, gameplay only gets the input it didn't use
         {
-            if let Some(mut health_components) = self.borrow_component_vector_mut::<Health>() {
-                health_system(&mut health_components);
+            if let Scenes::Ingame = self.current_scene {
+                if key_pressed(&self.keyboard_input_queue, winit::event::VirtualKeyCode::P) {
+                    self.set_paused(!self.paused);
+                }
+            }
+            self.keyboard_input_queue = self.ui.handle_input(&self.keyboard_input_queue);
+            for event in self.ui.take_events() {
+                match event {
+                    UiEvent::Pressed(id) => match id.as_str() {
+                        "resume" => self.set_paused(false),
+                        "restart" => scene_swap_opt = Some(Scenes::Ingame),
+                        "quit" => return true,
+                        _ => {},
+                    },
+                }
+            }
+            if self.paused || scene_swap_opt.is_some() {
+                self.keyboard_input_queue.clear();
+                self.step_input.clear();
+                if let Some(scene_to_swap) = scene_swap_opt {
+                    self.swap_scene(scene_to_swap, renderer, scene_swap_args);
+                }
+                self.ui.layout(renderer);
+                self.stats.lap("ui_layout");
+                return false;
             }
         }
+        self.stats.lap("ui_input");
+        // Whatever the UI didn't use waits for the next simulation step
+        self.step_input.append(&mut self.keyboard_input_queue);
 
-        // Player movement system
+        // The simulation runs in fixed steps so it plays the same at any frame rate. Past MAX_STEPS_PER_FRAME steps
+        // the time gets dropped, after a hitch the game slows down for a moment instead of trying to catch up
+        self.step_accumulator = (self.step_accumulator + time_passed.as_secs_f32() * 1000.).min(FIXED_TIMESTEP_MS * MAX_STEPS_PER_FRAME as f32);
+        let mut steps = 0;
+        while self.step_accumulator >= FIXED_TIMESTEP_MS && scene_swap_opt.is_none() {
+            self.step_accumulator -= FIXED_TIMESTEP_MS;
+            steps += 1;
+            if let Some((scene, args)) = self.simulation_step() {
+                scene_swap_opt = Some(scene);
+                scene_swap_args = args;
+            }
+        }
+        // How far the next step is along, entities get drawn that far between their last two positions
+        self.step_alpha = self.step_accumulator / FIXED_TIMESTEP_MS;
+        if let Some(physics_benchmark) = &mut self.physics_benchmark {
+            let times = [self.stats.system_time("broadphase"), self.stats.system_time("physics"), self.stats.system_time("collision")];
+            physics_benchmark.record_frame(steps, times);
+        }
+
+        // Everything from here on runs once a frame, timers and looks rather than simulation
+        let time_passed = time_passed.as_secs_f32() * 1000.;
+
+        // Particles system
         {
-            if let Some(mut velocity_components) = self.borrow_component_vector_mut::<Velocity>() {
-                player_movement_system(&mut velocity_components, self.player_index, &self.keyboard_input_queue);
+            if let (
+                Some(mut particle_emitter_components),
+                Some(position_components),
+            ) = (
+                self.borrow_component_vector_mut::<ParticleEmitter>(),
+                self.borrow_component_vector_mut::<Position>(),
+            ) {
+                let mut marked_for_deletion_components = self.borrow_component_vector_mut::<MarkedForDeletion>();
+                particles_system(&mut particle_emitter_components, &position_components, &mut marked_for_deletion_components, time_passed);
+            }
+        }
+        self.stats.lap("particles");
+
+        // Player animation system
+        {
+            if let (
+                Some(mut velocity_components),
+                Some(mut animation_map_components),
+            ) = (
+                self.borrow_component_vector_mut::<Velocity>(), 
+                self.borrow_component_vector_mut::<AnimationMap>(), 
+            ) {
+                player_animation_system(&mut velocity_components, &mut animation_map_components, self.player_index);
             }
         }
+        self.stats.lap("player_animation");
 
         // Animation system
         {
@@ -383,6 +903,85 @@ impl Game {
             let mut animation_map_components = self.borrow_component_vector_mut::<AnimationMap>();
             animation_system(&mut animation_components, &mut animation_map_components, time_passed);
         }
+        self.stats.lap("animation");
+
+        // Spin system
+        {
+            if let (Some(spin_components), Some(mut sprite_components)) = (self.borrow_component_vector_mut::<Spin>(), self.borrow_component_vector_mut::<Sprite>()) {
+                spin_system(&spin_components, &mut sprite_components, time_passed);
+            }
+        }
+        self.stats.lap("spin");
+
+        // Special system that removes unused entities, finished particle effects here
+        self.delete_marked_entities();
+        self.stats.lap("entity_cleanup");
+
+        // Camera system, following the player where it's drawn rather than where the last step left it
+        {
+            let position_components = self.render_positions();
+            let mut camera = Camera {x: self.camera.x, y: self.camera.y};
+            if let Some(rigid_body_components) = self.borrow_component_vector_mut::<RigidBody>() {
+                camera_system(&mut camera, &position_components, &rigid_body_components, self.player_index, self.level_size);
+            }
+            self.camera = camera;
+        }
+        self.stats.lap("camera");
+
+        // HUD
+        {
+            if let Some(player_index) = self.player_index {
+                let mut points_text = None;
+                let mut point_timer = None;
+                if let Some(points_component_vector) = self.borrow_component_vector_mut::<Points>() {
+                    if let Some(Some(player_points)) = points_component_vector.get(player_index) {
+                        points_text = Some(player_points.points.to_string());
+                        point_timer = Some(1. - player_points.time_since_last_point_change_ms / 1000.);
+                    }
+                }
+                if let (Some(points_text), Some(point_timer)) = (points_text, point_timer) {
+                    self.ui.set_text("points", 1, &points_text);
+                    self.ui.set_progress("point_timer", point_timer);
+                }
+            }
+        }
+        self.stats.lap("hud");
+
+        if let Some(scene_to_swap) = scene_swap_opt {
+            self.swap_scene(scene_to_swap, renderer, scene_swap_args);
+            self.stats.lap("scene_swap");
+        }
+        self.ui.layout(renderer);
+        self.stats.lap("ui_layout");
+
+        false
+    }
+
+    // One FIXED_TIMESTEP_MS step of everything that moves or collides. Returns the scene to go to when the step ended the current one
+    fn simulation_step(&mut self) -> Option<(Scenes, Option<Vec<Box<dyn Any>>>)> {
+        let mut scene_swap_opt: Option<Scenes> = None;
+        let mut scene_swap_args: Option<Vec<Box<dyn Any>>> = None;
+
+        // Where everything was before this step, for drawing in between
+        self.previous_positions = self.borrow_component_vector_mut::<Position>()
+            .map(|position_components| position_components.to_vec())
+            .unwrap_or_default();
+
+        // Health system
+        {
+            if let Some(mut health_components) = self.borrow_component_vector_mut::<Health>() {
+                health_system(&mut health_components);
+            }
+        }
+        self.stats.lap("health");
+
+        // Player movement system
+        {
+            if let Some(mut velocity_components) = self.borrow_component_vector_mut::<Velocity>() {
+                player_movement_system(&mut velocity_components, self.player_index, &self.step_input);
+            }
+        }
+        self.stats.lap("player_movement");
 
         // Gravity system
         {
@@ -393,11 +992,30 @@ impl Game {
                  self.borrow_component_vector_mut::<Gravity>(),
                  self.borrow_component_vector_mut::<Velocity>()
             ) {
-                gravity_system(&mut gravity_components, &mut velocity_components, time_passed);
+                gravity_system(&mut gravity_components, &mut velocity_components, FIXED_TIMESTEP_MS);
             }
         }
+        self.stats.lap("gravity");
+
+        // Remember how fast the player was falling so landing can be detected after moving
+        let player_vel_y_before_movement = self.player_index.and_then(|player_index| {
+            let velocity_components = self.borrow_component_vector_mut::<Velocity>()?;
+            let velocity = velocity_components.get(player_index)?.as_ref()?;
+            Some(velocity.vel_y)
+        });
+
+        // Broadphase, shared by physics and collisions. Physics moves things around in it, so it's still right for the collisions after.
+        // Taken out of self for as long as the systems need it, then put back to reuse its cells next step
+        let mut spatial_hash = std::mem::take(&mut self.spatial_hash);
+        {
+            if let (Some(position_components), Some(rigid_body_components)) = (self.borrow_component_vector_mut::<Position>(), self.borrow_component_vector_mut::<RigidBody>()) {
+                spatial_hash.rebuild(&position_components, &rigid_body_components);
+            }
+        }
+        self.stats.lap("broadphase");
 
         // Movement system
+        let mut contacts: Vec<Contact> = Vec::new();
         {
             if let (
                 Some(mut velocity_components), 
@@ -410,9 +1028,26 @@ impl Game {
                 self.borrow_component_vector_mut::<RigidBody>(),
                 self.borrow_component_vector_mut::<BlocksMovement>(),
             ) {
-                physics_system(&mut velocity_components, &mut position_components, &mut rigid_body_components, &mut blocks_movement, time_passed);
+                contacts = physics_system(&mut velocity_components, &mut position_components, &mut rigid_body_components, &mut blocks_movement, &mut spatial_hash, FIXED_TIMESTEP_MS);
+            }
+        }
+        self.stats.lap("physics");
+
+        // Landing dust
+        {
+            if let (Some(player_index), Some(vel_y_before)) = (self.player_index, player_vel_y_before_movement) {
+                // Something's floor stopped the fall
+                let landed = contacts.iter().any(|contact| contact.entity == player_index && contact.normal[1] < 0.);
+                if let Some(mut particle_emitter_components) = self.borrow_component_vector_mut::<ParticleEmitter>() {
+                    if let Some(Some(emitter)) = particle_emitter_components.get_mut(player_index) {
+                        if vel_y_before > LANDING_DUST_MIN_VELOCITY && landed {
+                            emitter.burst += 12;
+                        }
+                    }
+                }
             }
         }
+        self.stats.lap("landing_dust");
 
         // Collision system
         {
@@ -429,23 +1064,13 @@ impl Game {
                     &mut position_components, 
                     &mut rigid_body_components,
                     &mut collision_list_components,
-                    time_passed,
+                    &spatial_hash,
+                    FIXED_TIMESTEP_MS,
                 );
             }
         }
-
-        // Player animation system
-        {
-            if let (
-                Some(mut velocity_components),
-                Some(mut animation_map_components),
-            ) = (
-                self.borrow_component_vector_mut::<Velocity>(), 
-                self.borrow_component_vector_mut::<AnimationMap>(), 
-            ) {
-                player_animation_system(&mut velocity_components, &mut animation_map_components, self.player_index);
-            }
-        }
+        self.spatial_hash = spatial_hash;
+        self.stats.lap("collision");
 
         // Player pineapple system
         {
@@ -463,6 +1088,33 @@ impl Game {
                 player_pineapple_system(&collision_list_components, &mut marked_for_deletion_components, &entity_type_components, &mut points_components, self.player_index);
             }
         }
+        self.stats.lap("player_pineapple");
+
+        // Sparkles where pineapples got picked up
+        {
+            let mut collected_positions: Vec<(f32, f32)> = Vec::new();
+            if let (
+                Some(marked_for_deletion_components),
+                Some(entity_type_components),
+                Some(position_components),
+            ) = (
+                self.borrow_component_vector_mut::<MarkedForDeletion>(),
+                self.borrow_component_vector_mut::<EntityType>(),
+                self.borrow_component_vector_mut::<Position>(),
+            ) {
+                let zip = marked_for_deletion_components.iter().zip(entity_type_components.iter().zip(position_components.iter()));
+                let iter = zip.filter_map(|(marked_for_deletion, (entity_type, position))| Some((marked_for_deletion.as_ref()?, entity_type.as_ref()?, position.as_ref()?)));
+                for (marked_for_deletion, entity_type, position) in iter {
+                    if let (true, EntityType::Pineapple) = (marked_for_deletion.marked, entity_type) {
+                        collected_positions.push((position.x, position.y));
+                    }
+                }
+            }
+            for (x, y) in collected_positions {
+                self.spawn_sparkles(x, y);
+            }
+        }
+        self.stats.lap("sparkles");
 
         // Flag reached system
         if let (
@@ -485,6 +1137,7 @@ impl Game {
                 }
             }
         }
+        self.stats.lap("flag_reached");
         
         // Points ticking down system
         if let (
@@ -492,37 +1145,65 @@ impl Game {
         ) = (
             self.borrow_component_vector_mut::<Points>(),
         ) {
-            if points_ticking_down(&mut points_components, time_passed, self.player_index) {
+            if points_ticking_down(&mut points_components, FIXED_TIMESTEP_MS, self.player_index) {
                 scene_swap_opt = Some(Scenes::GameOver);
             }
         }
+        self.stats.lap("points_ticking_down");
 
-        // Clear up the keyboard input queue
-        {
-            self.keyboard_input_queue.clear();
-        }
+        // Special system that removes unused entities, right away so nothing gets picked up twice
+        self.delete_marked_entities();
+        self.stats.lap("entity_cleanup");
 
-        // Special system that removes unused entities
-        {
-            let mut entities_for_deletion: Vec<usize> = Vec::new();
-            if let Some(marked_for_deletion_components) = self.borrow_component_vector_mut::<MarkedForDeletion>() {
-                let iter = marked_for_deletion_components.iter().enumerate();
-                for (marked_for_deletion, index) in iter.filter_map(|(index, marked_for_deletion)| Some((marked_for_deletion.as_ref()?, index))) {
-                    if marked_for_deletion.marked {
-                        entities_for_deletion.push(index);
-                    }
+        // Input only counts for the first step it's there for
+        self.step_input.clear();
+
+        scene_swap_opt.map(|scene| (scene, scene_swap_args))
+    }
+
+    fn delete_marked_entities(&mut self) {
+        let mut entities_for_deletion: Vec<usize> = Vec::new();
+        if let Some(marked_for_deletion_components) = self.borrow_component_vector_mut::<MarkedForDeletion>() {
+            let iter = marked_for_deletion_components.iter().enumerate();
+            for (marked_for_deletion, index) in iter.filter_map(|(index, marked_for_deletion)| Some((marked_for_deletion.as_ref()?, index))) {
+                if marked_for_deletion.marked {
+                    entities_for_deletion.push(index);
                 }
             }
-            for index_to_delete in entities_for_deletion {
-                self.delete_entity(index_to_delete);
-            }
         }
-
-        if let Some(scene_to_swap) = scene_swap_opt {
-            self.swap_scene(scene_to_swap, renderer, scene_swap_args);
+        for index_to_delete in entities_for_deletion {
+            self.delete_entity(index_to_delete);
         }
+    }
 
-        false
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.ui.set_visible("pause_menu", paused);
+    }
+
+    fn spawn_sparkles(&mut self, x: f32, y: f32) {
+        let sparkles_index = self.add_entity();
+        self.add_component_to_entity(sparkles_index, Position {x, y});
+        self.add_component_to_entity(sparkles_index, MarkedForDeletion {marked: false});
+        self.add_component_to_entity(sparkles_index, ParticleEmitter {
+            texture_id: self.particle_texture_id,
+            emitting: false,
+            burst: 16,
+            despawn_when_done: true,
+            offset: [32. / self.target_resolution[0] as f32, 32. / self.target_resolution[1] as f32],
+            spawn_area: [32. / self.target_resolution[0] as f32, 32. / self.target_resolution[1] as f32],
+            lifetime_ms: [300, 600],
+            vel_x: [-0.3, 0.3],
+            vel_y: [-0.4, 0.1],
+            gravity: [0.5, 0.5],
+            start_color: [1., 1., 0.6, 1.],
+            end_color: [1., 0.8, 0.2, 0.],
+            start_size: [8. / self.target_resolution[0] as f32, 8. / self.target_resolution[1] as f32],
+            end_size: [2. / self.target_resolution[0] as f32, 2. / self.target_resolution[1] as f32],
+            z: 11,
+            rng_state: 0x9E37_79B9 ^ (sparkles_index as u32).wrapping_mul(747_796_405) | 1,
+            ..Default::default()
+        });
     }
 
     pub fn process_keyboard_input(&mut self, input: &winit::event::KeyboardInput) {
@@ -532,35 +1213,44 @@ impl Game {
 
     fn get_world_renderables(&self) -> Vec<Renderable> {
         let mut to_return: Vec<Renderable> = Vec::new();
-        let mut z_buffer: Vec<u32> = Vec::new(); // TODO: Could do Z-checks work on a GPU instead proly
 
         // Sprite rendering function
+        let camera = &self.camera;
         let mut render_sprite = |position: &Position, sprite: &Sprite, horiz_mirror: bool| {
-            if sprite.render {
-                let (x1, y1) = (position.x, position.y);
-                let (x2, y2) = (position.x + sprite.width_normalized, position.y + sprite.height_normalized);
-                let new_renderable = Renderable{ p1: [x1, y1], p2: [x2, y2], texture_id: sprite.texture_id, use_texture_size: false, horiz_mirror };
-                if to_return.is_empty() {
-                    to_return.push(new_renderable);
-                    z_buffer.push(sprite.z);
-                } else {
-                    // find the spot for the new thing
-                    let mut new_index = to_return.len();
-                    for (i, _) in to_return.iter().enumerate() {
-                        if z_buffer.get(i).unwrap() < &sprite.z {
-                            new_index = i;
-                            break;
-                        }
-                    }
-                    to_return.insert(new_index, new_renderable);
-                    z_buffer.insert(new_index, sprite.z);
-                }
+            // UI sprites stay put on screen, get_ui_renderables takes care of them
+            if sprite.render && sprite.layer != RenderLayer::Ui {
+                let (x1, y1) = (position.x - camera.x, position.y - camera.y);
+                let (x2, y2) = (x1 + sprite.width_normalized, y1 + sprite.height_normalized);
+                let new_renderable = Renderable{
+                    p1: [x1, y1],
+                    p2: [x2, y2],
+                    texture_id: sprite.texture_id,
+                    layer: sprite.layer,
+                    z: sprite.z,
+                    use_texture_size: false,
+                    horiz_mirror,
+                    vert_mirror: sprite.vert_mirror,
+                    tint: sprite.tint,
+                    opacity: sprite.opacity,
+                    rotation: sprite.rotation,
+                    pivot: sprite.pivot,
+                    scale: sprite.scale,
+                    tex_rect: [0., 0., 1., 1.],
+                    material_id: sprite.material_id,
+                    material_params: sprite.material_params,
+                    normal_map_id: sprite.normal_map_id,
+                    lit: true,
+                    nine_slice: sprite.nine_slice,
+                };
+                to_return.push(new_renderable);
             }
         };
 
+        let positions = self.render_positions();
+
         // Render simple sprites
         {
-            if let (Some(sprites), Some(positions)) = (self.borrow_component_vector_mut::<Sprite>(), self.borrow_component_vector_mut::<Position>()) {
+            if let Some(sprites) = self.borrow_component_vector_mut::<Sprite>() {
                 let zip = positions.iter().zip(sprites.iter());
                 let iter = zip.filter_map(|(position, sprite)| Some((position.as_ref()?, sprite.as_ref()?)));
                 for (position, sprite) in iter {
@@ -571,7 +1261,7 @@ impl Game {
 
         // Render simple animations
         {
-            if let (Some(animations), Some(positions)) = (self.borrow_component_vector_mut::<Animation>(), self.borrow_component_vector_mut::<Position>()) {
+            if let Some(animations) = self.borrow_component_vector_mut::<Animation>() {
                 let zip = positions.iter().zip(animations.iter());
                 let iter = zip.filter_map(|(position, animation)| Some((position.as_ref()?, animation.as_ref()?)));
                 for (position, animation) in iter {
@@ -583,7 +1273,7 @@ impl Game {
 
         // Render animation maps
         {
-            if let (Some(animation_maps), Some(positions)) = (self.borrow_component_vector_mut::<AnimationMap>(), self.borrow_component_vector_mut::<Position>()) {
+            if let Some(animation_maps) = self.borrow_component_vector_mut::<AnimationMap>() {
                 let zip = positions.iter().zip(animation_maps.iter());
                 let iter = zip.filter_map(|(position, animation_map)| Some((position.as_ref()?, animation_map.as_ref()?)));
                 for (position, animation_map) in iter {
@@ -599,115 +1289,232 @@ impl Game {
         to_return
     }
 
+    fn get_parallax_renderables(&self) -> Vec<Renderable> {
+        let mut to_return: Vec<Renderable> = Vec::new();
+        if let Some(parallax_layers) = self.borrow_component_vector_mut::<ParallaxLayer>() {
+            for layer in parallax_layers.iter().filter_map(|layer| layer.as_ref()) {
+                let [tile_width, tile_height] = layer.tile_size;
+                // How far the layer has moved away from the screen's top left corner
+                let scroll_x = self.camera.x * layer.scroll_factor[0] - layer.offset[0];
+                let anchor_y = match layer.vertical_anchor {
+                    VerticalAnchor::Top => 0.,
+                    VerticalAnchor::Center => 0.5 - tile_height / 2.,
+                    VerticalAnchor::Bottom => 1. - tile_height,
+                } + layer.offset[1] - self.camera.y * layer.scroll_factor[1];
+
+                // Repeating layers cover the whole screen and let the sampler do the tiling
+                let (x1, x2, u1, u2) = if layer.repeat_x {
+                    (0., 1., scroll_x / tile_width, (scroll_x + 1.) / tile_width)
+                } else {
+                    (-scroll_x, tile_width - scroll_x, 0., 1.)
+                };
+                let (y1, y2, v1, v2) = if layer.repeat_y {
+                    (0., 1., -anchor_y / tile_height, (1. - anchor_y) / tile_height)
+                } else {
+                    (anchor_y, anchor_y + tile_height, 0., 1.)
+                };
+
+                to_return.push(Renderable {
+                    texture_id: layer.texture_id,
+                    layer: RenderLayer::Background,
+                    z: layer.z,
+                    p1: [x1, y1],
+                    p2: [x2, y2],
+                    tex_rect: [u1, v1, u2, v2],
+                    ..Default::default()
+                });
+            }
+        }
+        to_return
+    }
+
+    fn get_world_particles(&self) -> Vec<RenderableParticles> {
+        let mut to_return: Vec<RenderableParticles> = Vec::new();
+        if let (Some(particle_emitters), Some(positions)) = (self.borrow_component_vector_mut::<ParticleEmitter>(), self.borrow_component_vector_mut::<Position>()) {
+            let zip = positions.iter().zip(particle_emitters.iter());
+            let iter = zip.filter_map(|(position, particle_emitter)| Some((position.as_ref()?, particle_emitter.as_ref()?)));
+            for (position, emitter) in iter {
+                if emitter.particles.is_empty() {
+                    continue;
+                }
+                let lerp = |from: f32, to: f32, t: f32| from + (to - from) * t;
+                let particles = emitter.particles.iter().map(|particle| {
+                    let t = particle_progress(particle);
+                    ParticleQuad {
+                        center: [particle.x - self.camera.x, particle.y - self.camera.y],
+                        size: [lerp(emitter.start_size[0], emitter.end_size[0], t), lerp(emitter.start_size[1], emitter.end_size[1], t)],
+                        color: [
+                            lerp(emitter.start_color[0], emitter.end_color[0], t),
+                            lerp(emitter.start_color[1], emitter.end_color[1], t),
+                            lerp(emitter.start_color[2], emitter.end_color[2], t),
+                            lerp(emitter.start_color[3], emitter.end_color[3], t),
+                        ],
+                    }
+                }).collect();
+                to_return.push(RenderableParticles {
+                    texture_id: emitter.texture_id,
+                    layer: emitter.layer,
+                    z: emitter.z,
+                    sort_y: position.y + emitter.offset[1] - self.camera.y,
+                    particles,
+                });
+            }
+        }
+        to_return
+    }
+
     fn get_ui_renderables(&self) -> (Vec<Renderable>, Vec<RenderableText>) {
-        // TODO: Render ui
         let mut renderables: Vec<Renderable> = Vec::new();
-        let mut z_buffer: Vec<u32> = Vec::new(); // TODO: Could do Z-checks work on a GPU instead proly
 
         // Sprite rendering function
         let mut render_sprite = |position: &Position, sprite: &Sprite, horiz_mirror: bool| {
             if sprite.render {
                 let (x1, y1) = (position.x, position.y);
                 let (x2, y2) = (position.x + sprite.width_normalized, position.y + sprite.height_normalized);
-                let new_renderable = Renderable{ p1: [x1, y1], p2: [x2, y2], texture_id: sprite.texture_id, use_texture_size: false, horiz_mirror };
-                if renderables.is_empty() {
-                    renderables.push(new_renderable);
-                    z_buffer.push(sprite.z);
-                } else {
-                    // find the spot for the new thing
-                    let mut new_index = renderables.len();
-                    for (i, _) in renderables.iter().enumerate() {
-                        if z_buffer.get(i).unwrap() < &sprite.z {
-                            new_index = i;
-                            break;
-                        }
-                    }
-                    renderables.insert(new_index, new_renderable);
-                    z_buffer.insert(new_index, sprite.z);
-                }
+                let new_renderable = Renderable{
+                    p1: [x1, y1],
+                    p2: [x2, y2],
+                    texture_id: sprite.texture_id,
+                    layer: RenderLayer::Ui,
+                    z: sprite.z,
+                    use_texture_size: false,
+                    horiz_mirror,
+                    vert_mirror: sprite.vert_mirror,
+                    tint: sprite.tint,
+                    opacity: sprite.opacity,
+                    rotation: sprite.rotation,
+                    pivot: sprite.pivot,
+                    scale: sprite.scale,
+                    tex_rect: [0., 0., 1., 1.],
+                    material_id: sprite.material_id,
+                    material_params: sprite.material_params,
+                    normal_map_id: sprite.normal_map_id,
+                    lit: false,
+                    nine_slice: sprite.nine_slice,
+                };
+                renderables.push(new_renderable);
             }
         };
-        let mut renderable_texts: Vec<RenderableText> = Vec::new();
 
-        match self.current_scene {
-            Scenes::Ingame => {
-                // Points
-                {
-                    if let Some(player_index) = self.player_index {
-                        if let Some(points_component_vector) = self.borrow_component_vector_mut::<Points>() {
-                            if let Some(Some(player_points)) = points_component_vector.get(player_index) {
-                                let points_prefix: String = "Points: ".to_owned();
-                                renderable_texts.push(RenderableText {
-                                    color: [1., 0., 0., 1.],
-                                    size: 16.,
-                                    text: (points_prefix + &player_points.points.to_string()),
-                                    x: 0.02,
-                                    y: 0.02,
-                                });
-                            }
-                        }
-                    }
-                }
-            },
-            Scenes::GameOver => {
-                renderable_texts.push(RenderableText {
-                    color: [1., 1., 1., 1.],
-                    size: 64.,
-                    text: "Game Over".to_owned(),
-                    x: 0.27,
-                    y: 0.4,
-                });
-                renderable_texts.push(RenderableText {
-                    color: [1., 1., 1., 1.],
-                    size: 32.,
-                    text: "Press (ESC) to exit.".to_owned(),
-                    x: 0.25,
-                    y: 0.8,
-                });
-            },
-            Scenes::YouWon => {
-                renderable_texts.push(RenderableText {
-                    color: [1., 1., 1., 1.],
-                    size: 64.,
-                    text: "You won!".to_owned(),
-                    x: 0.3,
-                    y: 0.4,
-                });
-                // Points
-                {
-                    if let Some(points_component_vector) = self.borrow_component_vector_mut::<Points>() {
-                        if let Some(Some(player_points)) = points_component_vector.get(0) {
-                            let points_prefix: String = "Points: ".to_owned();
-                            renderable_texts.push(RenderableText {
-                                color: [1., 0.5, 0.5, 1.],
-                                size: 32.,
-                                text: (points_prefix + &player_points.points.to_string()),
-                                x: 0.36,
-                                y: 0.6,
-                            });
-                        }
-                    }
+        // Panels and the like, placed in screen space
+        {
+            if let (Some(sprites), Some(positions)) = (self.borrow_component_vector_mut::<Sprite>(), self.borrow_component_vector_mut::<Position>()) {
+                let zip = positions.iter().zip(sprites.iter());
+                let iter = zip.filter_map(|(position, sprite)| Some((position.as_ref()?, sprite.as_ref()?)));
+                for (position, sprite) in iter.filter(|(_, sprite)| sprite.layer == RenderLayer::Ui) {
+                    render_sprite(&position, &sprite, false);
                 }
-                renderable_texts.push(RenderableText {
-                    color: [1., 1., 1., 1.],
-                    size: 16.,
-                    text: "Press (ESC) to exit.".to_owned(),
-                    x: 0.36,
-                    y: 0.8,
-                });
             }
         }
+        let (mut ui_renderables, renderable_texts) = self.ui.renderables();
+        renderables.append(&mut ui_renderables);
         
         (renderables, renderable_texts)
     }
 
-    pub fn get_renderables(&self) -> (Vec<Renderable>, Vec<RenderableText>) {
+    // None renders the scene unlit
+    pub fn get_lighting(&self) -> Option<SceneLighting> {
+        let ambient_light = self.borrow_component_vector_mut::<AmbientLight>()
+            .and_then(|ambient_lights| ambient_lights.iter().find_map(|ambient_light| ambient_light.as_ref().map(|light| light.color.map(|c| c * light.intensity))));
+
+        let mut lights: Vec<RenderableLight> = Vec::new();
+        let positions = self.render_positions();
+        if let Some(point_lights) = self.borrow_component_vector_mut::<PointLight>() {
+            let zip = positions.iter().zip(point_lights.iter());
+            let iter = zip.filter_map(|(position, point_light)| Some((position.as_ref()?, point_light.as_ref()?)));
+            for (position, point_light) in iter {
+                lights.push(RenderableLight {
+                    position: [position.x + point_light.offset[0] - self.camera.x, position.y + point_light.offset[1] - self.camera.y],
+                    color: point_light.color,
+                    intensity: point_light.intensity,
+                    radius: point_light.radius,
+                    height: point_light.height,
+                });
+            }
+        }
+
+        if ambient_light.is_none() && lights.is_empty() {
+            return None;
+        }
+        Some(SceneLighting {ambient: ambient_light.unwrap_or([1., 1., 1.]), lights})
+    }
+
+    // Frame time graph, what the renderer did last frame and how long each system took
+    pub fn draw_stats_overlay(&self, debug: &mut DebugDraw) {
+        self.stats.draw_overlay(debug);
+    }
+
+    // Rigid body boxes (red when they block movement), the sides currently touching something and where things are headed
+    pub fn draw_physics_debug(&self, debug: &mut DebugDraw) {
+        let positions = self.render_positions();
+        let rigid_bodies = match self.borrow_component_vector_mut::<RigidBody>() {
+            Some(rigid_bodies) => rigid_bodies,
+            None => return,
+        };
+        let blocks_movement = self.borrow_component_vector_mut::<BlocksMovement>();
+        let collision_lists = self.borrow_component_vector_mut::<CollisionList>();
+        let velocities = self.borrow_component_vector_mut::<Velocity>();
+
+        let iter = positions.iter().zip(rigid_bodies.iter()).enumerate();
+        let iter = iter.filter_map(|(index, (position, rigid_body))| Some((position.as_ref()?, rigid_body.as_ref()?, index)));
+        for (position, rigid_body, index) in iter {
+            let p1 = [position.x - self.camera.x, position.y - self.camera.y];
+            let p2 = [p1[0] + rigid_body.width, p1[1] + rigid_body.height];
+            let blocks = matches!(blocks_movement.as_ref().and_then(|blocks_movement| blocks_movement.get(index)), Some(Some(BlocksMovement {blocks: true})));
+            let color = if blocks { [1., 0.2, 0.2, 0.8] } else { [0.2, 1., 0.2, 0.8] };
+            debug.rect(p1, p2, color);
+
+            if let Some(Some(collision_list)) = collision_lists.as_ref().and_then(|collision_lists| collision_lists.get(index)) {
+                for collision in collision_list.list.iter() {
+                    let (from, to) = match collision.side {
+                        CollisionSide::LEFT => ([p1[0], p1[1]], [p1[0], p2[1]]),
+                        CollisionSide::RIGHT => ([p2[0], p1[1]], [p2[0], p2[1]]),
+                        CollisionSide::TOP => ([p1[0], p1[1]], [p2[0], p1[1]]),
+                        CollisionSide::BOTTOM => ([p1[0], p2[1]], [p2[0], p2[1]]),
+                    };
+                    debug.line(from, to, [1., 1., 0., 1.]);
+                }
+            }
+
+            if let Some(Some(velocity)) = velocities.as_ref().and_then(|velocities| velocities.get(index)) {
+                // Where it'll be in a quarter of a second at this speed
+                let center = [(p1[0] + p2[0]) / 2., (p1[1] + p2[1]) / 2.];
+                let to = [center[0] + velocity.vel_x * 0.25, center[1] + velocity.vel_y * 0.25];
+                debug.line(center, to, [0.2, 0.9, 1., 1.]);
+                debug.circle(to, 0.004, [0.2, 0.9, 1., 1.]);
+                debug.text([p1[0], p1[1] - 0.015], &format!("{:.2}, {:.2}", velocity.vel_x, velocity.vel_y), [0.2, 0.9, 1., 1.]);
+            }
+        }
+    }
+
+    pub fn get_renderables(&self) -> (Vec<Renderable>, Vec<RenderableParticles>, Vec<RenderableText>) {
         let (mut ui_renderables, ui_renderable_texts) = self.get_ui_renderables();
         let mut world_renderables = self.get_world_renderables();
         ui_renderables.append(&mut world_renderables);
+        let mut parallax_renderables = self.get_parallax_renderables();
+        ui_renderables.append(&mut parallax_renderables);
+        let world_particles = self.get_world_particles();
 
         
-        (ui_renderables, ui_renderable_texts)
+        (ui_renderables, world_particles, ui_renderable_texts)
+    }
+
+    // Positions somewhere between the last two simulation steps, for drawing. Entities that showed up in the last step are where they are
+    fn render_positions(&self) -> Vec<Option<Position>> {
+        let position_components = match self.borrow_component_vector_mut::<Position>() {
+            Some(position_components) => position_components,
+            None => return Vec::new(),
+        };
+        position_components.iter().enumerate().map(|(index, position)| {
+            let position = position.as_ref()?;
+            Some(match self.previous_positions.get(index) {
+                Some(Some(previous)) => Position {
+                    x: previous.x + (position.x - previous.x) * self.step_alpha,
+                    y: previous.y + (position.y - previous.y) * self.step_alpha,
+                },
+                _ => *position,
+            })
+        }).collect()
     }
 
     fn add_entity(&mut self) -> usize {
@@ -764,6 +1571,13 @@ impl Game {
             components_vector.remove_component_for_entity(entity_index);
         }
     }
+
+    // Deleted entities keep their index, so only the ones with a component left count
+    fn live_entity_count(&self) -> usize {
+        (0..self.entity_count)
+            .filter(|entity_index| self.component_vectors.iter().any(|components_vector| components_vector.has_component_for_entity(*entity_index)))
+            .count()
+    }
 }
 
 trait ComponentsVector {
@@ -771,6 +1585,7 @@ trait ComponentsVector {
     fn as_any_mut(&mut self) -> &mut dyn std::any::Any;
     fn push_none(&mut self);
     fn remove_component_for_entity(&mut self, entity_index: usize);
+    fn has_component_for_entity(&self, entity_index: usize) -> bool;
 }
 
 impl<T: 'static> ComponentsVector for RefCell<Vec<Option<T>>> {
@@ -788,4 +1603,62 @@ impl<T: 'static> ComponentsVector for RefCell<Vec<Option<T>>> {
             *comp = None;
         }
     }
-}
\ No newline at end of file
+    fn has_component_for_entity(&self, entity_index: usize) -> bool {
+        // Someone holding the vector means it's in use, count it
+        self.try_borrow().map_or(true, |components| matches!(components.get(entity_index), Some(Some(_))))
+    }
+}
+
+fn key_pressed(inputs: &Vec<winit::event::KeyboardInput>, key: winit::event::VirtualKeyCode) -> bool {
+    inputs.iter().any(|input| input.state == winit::event::ElementState::Pressed && input.virtual_keycode == Some(key))
+}
+
+// Textures that fail to register show up magenta instead of taking the game down
+fn or_missing(texture: Result<usize, RendererError>, renderer: &Renderer) -> usize {
+    texture.unwrap_or_else(|error| {
+        println!("{}", error);
+        renderer.missing_texture()
+    })
+}
+
+fn panel_background(panel_texture: usize, tint: [f32; 4]) -> Background {
+    Background {texture_id: panel_texture, nine_slice: Some(NineSlice {borders: [8., 8., 8., 8.], border_scale: 2.}), tint}
+}
+
+fn anchored(anchor: [f32; 2], offset: [f32; 2], child: Widget) -> Widget {
+    Widget::new(WidgetKind::Anchor {anchor, offset, child: Box::new(child)})
+}
+
+fn title(text: &str, shadow: Option<TextShadow>) -> Widget {
+    Widget {
+        padding: [0., 0.02],
+        ..Widget::new(WidgetKind::Label {
+            spans: vec![TextSpan {text: text.to_owned(), color: [1., 1., 1., 1.], size: 64., ..Default::default()}],
+            outline: None,
+            shadow,
+        })
+    }
+}
+
+// Centered column on a panel, for the pause and end screens
+fn menu_panel(panel_texture: usize, children: Vec<Widget>) -> Widget {
+    Widget {
+        background: Some(panel_background(panel_texture, [1., 1., 1., 1.])),
+        padding: [0.04, 0.05],
+        min_size: [0.4, 0.],
+        ..Widget::new(WidgetKind::Column {spacing: 0.02, align: HorizontalAlign::Center, children})
+    }
+}
+
+fn menu_button(panel_texture: usize, id: &str, text: &str, font: FontId) -> Widget {
+    Widget {
+        id: id.to_owned(),
+        background: Some(panel_background(panel_texture, [0.6, 0.6, 0.7, 1.])),
+        padding: [0.02, 0.02],
+        min_size: [0.25, 0.],
+        ..Widget::new(WidgetKind::Button {
+            label: vec![TextSpan {text: text.to_owned(), font, color: [1., 1., 1., 1.], size: 24., ..Default::default()}],
+            focus_tint: [1., 0.85, 0.4, 1.],
+        })
+    }
+}
@@ -0,0 +1,42 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+
+layout(set = 0, binding = 0) uniform texture2D t_albedo;
+layout(set = 0, binding = 1) uniform texture2D t_normal;
+layout(set = 0, binding = 2) uniform sampler s_screen;
+
+struct Light {
+    vec2 position; // 0 to 1 across the screen
+    float radius; // In screen heights
+    float height; // How far above the screen, also in screen heights
+    vec4 color; // Already multiplied by the intensity
+};
+
+layout(std430, set = 0, binding = 3) readonly buffer Lights {
+    vec4 u_ambient;
+    uvec4 u_light_count;
+    Light lights[];
+};
+
+layout(location=0) out vec4 f_color;
+
+void main() {
+    vec4 albedo = texture(sampler2D(t_albedo, s_screen), v_tex_coords);
+    // xy: screen space normal, z: whether lights affect this pixel at all
+    vec4 normal_data = texture(sampler2D(t_normal, s_screen), v_tex_coords);
+    vec3 normal = vec3(normal_data.xy * 2.0 - 1.0, 0.0);
+    normal.z = sqrt(max(0.0, 1.0 - dot(normal.xy, normal.xy)));
+
+    vec2 resolution = vec2(textureSize(sampler2D(t_albedo, s_screen), 0));
+    vec2 pixel = v_tex_coords * resolution;
+    vec3 light = u_ambient.rgb;
+    for (uint i = 0; i < u_light_count.x; i++) {
+        vec2 to_light = lights[i].position * resolution - pixel;
+        float attenuation = clamp(1.0 - length(to_light) / (lights[i].radius * resolution.y), 0.0, 1.0);
+        vec3 direction = normalize(vec3(to_light, lights[i].height * resolution.y));
+        light += lights[i].color.rgb * attenuation * attenuation * max(dot(normal, direction), 0.0);
+    }
+
+    f_color = vec4(albedo.rgb * mix(vec3(1.0), light, normal_data.z), albedo.a);
+}
\ No newline at end of file
@@ -0,0 +1,253 @@
+use crate::post_process::RenderTarget;
+use crate::stats::RenderStats;
+use wgpu::util::DeviceExt;
+
+pub const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+pub const DEFAULT_MAX_LIGHTS: usize = 32;
+
+// Same layout as the Lights buffer in lighting.frag
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct LightsHeader {
+    ambient: [f32; 4],
+    light_count: [u32; 4],
+}
+unsafe impl bytemuck::Pod for LightsHeader{}
+unsafe impl bytemuck::Zeroable for LightsHeader{}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct GpuLight {
+    position: [f32; 2],
+    radius: f32,
+    height: f32,
+    color: [f32; 4],
+}
+unsafe impl bytemuck::Pod for GpuLight{}
+unsafe impl bytemuck::Zeroable for GpuLight{}
+
+pub struct RenderableLight {
+    pub position: [f32; 2], // Normalized screen coordinates, like renderables
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub radius: f32, // Fraction of the screen height
+    pub height: f32, // Above the screen, low lights graze the normal maps more
+}
+
+pub struct SceneLighting {
+    pub ambient: [f32; 3],
+    pub lights: Vec<RenderableLight>,
+}
+
+// Deferred 2D lighting. Sprites get drawn into an albedo and a normal target, then a full screen pass
+// adds up the ambient and every point light for each pixel
+pub struct Lighting {
+    format: wgpu::TextureFormat,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    sampler: wgpu::Sampler,
+    albedo_target: Option<RenderTarget>,
+    normal_target: Option<RenderTarget>,
+    max_lights: usize,
+}
+
+impl Lighting {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let screen_texture_entry = |binding: u32| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float {filterable: false,},
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                screen_texture_entry(0),
+                screen_texture_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("lighting_bind_group_layout"),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                &bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        // Both targets are screen sized, so every pixel maps to exactly one texel
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let vs_module = device.create_shader_module(&wgpu::include_spirv!("post_effects/fullscreen.vert.spv"));
+        let fs_module = device.create_shader_module(&wgpu::include_spirv!("lighting.frag.spv"));
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("lighting_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vs_module,
+                entry_point: "main",
+                buffers: &[],
+            },
+            depth_stencil: None,
+            fragment: Some(wgpu::FragmentState {
+                module: &fs_module,
+                entry_point: "main",
+                targets: &[
+                    wgpu::ColorTargetState {
+                        format,
+                        write_mask: wgpu::ColorWrite::ALL,
+                        blend: None,
+                    }
+                ]
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            primitive: wgpu::PrimitiveState::default(),
+        });
+
+        Self {
+            format,
+            bind_group_layout,
+            pipeline,
+            sampler,
+            albedo_target: None,
+            normal_target: None,
+            max_lights: DEFAULT_MAX_LIGHTS,
+        }
+    }
+
+    pub fn set_max_lights(&mut self, max_lights: usize) {
+        self.max_lights = max_lights;
+    }
+
+    pub fn prepare(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        if let Some(target) = &self.albedo_target {
+            if target.width == width && target.height == height {
+                return;
+            }
+        }
+        self.albedo_target = Some(RenderTarget::new(device, width, height, self.format, "lighting_albedo"));
+        self.normal_target = Some(RenderTarget::new(device, width, height, NORMAL_FORMAT, "lighting_normals"));
+    }
+
+    pub fn albedo_view(&self) -> &wgpu::TextureView {
+        &self.albedo_target.as_ref().unwrap().view
+    }
+
+    pub fn normal_view(&self) -> &wgpu::TextureView {
+        &self.normal_target.as_ref().unwrap().view
+    }
+
+    // Lights the albedo target and writes the result into output
+    pub fn apply(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, scene_lighting: &SceneLighting, output: &wgpu::TextureView, stats: &mut RenderStats) {
+        let (width, height) = match &self.albedo_target {
+            Some(target) => (target.width as f32, target.height as f32),
+            None => return,
+        };
+        stats.fullscreen_pass();
+
+        // Skip lights that can't reach the screen, then keep the ones closest to its center
+        let mut visible: Vec<&RenderableLight> = scene_lighting.lights.iter().filter(|light| {
+            let radius_x = light.radius * height / width;
+            light.position[0] + radius_x > 0. && light.position[0] - radius_x < 1.
+                && light.position[1] + light.radius > 0. && light.position[1] - light.radius < 1.
+        }).collect();
+        let distance_to_center = |light: &RenderableLight| (light.position[0] - 0.5).powi(2) + (light.position[1] - 0.5).powi(2);
+        visible.sort_by(|a, b| distance_to_center(a).partial_cmp(&distance_to_center(b)).unwrap_or(std::cmp::Ordering::Equal));
+        visible.truncate(self.max_lights);
+
+        let header = LightsHeader {
+            ambient: [scene_lighting.ambient[0], scene_lighting.ambient[1], scene_lighting.ambient[2], 1.],
+            light_count: [visible.len() as u32, 0, 0, 0],
+        };
+        let mut contents: Vec<u8> = bytemuck::bytes_of(&header).to_vec();
+        for light in visible.iter() {
+            let gpu_light = GpuLight {
+                position: light.position,
+                radius: light.radius,
+                height: light.height,
+                color: [light.color[0] * light.intensity, light.color[1] * light.intensity, light.color[2] * light.intensity, 1.],
+            };
+            contents.extend_from_slice(bytemuck::bytes_of(&gpu_light));
+        }
+        // The shader's runtime sized array wants room for at least one light
+        if visible.is_empty() {
+            contents.extend_from_slice(bytemuck::bytes_of(&GpuLight { position: [0., 0.], radius: 0., height: 0., color: [0.; 4] }));
+        }
+        let lights_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("lights_buffer"),
+            contents: &contents,
+            usage: wgpu::BufferUsage::STORAGE,
+        });
+        stats.buffer_allocations += 1;
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(self.albedo_view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(self.normal_view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: lights_buffer.as_entire_binding(),
+                },
+            ],
+            label: None,
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[
+                wgpu::RenderPassColorAttachment {
+                    view: output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    }
+                }
+            ],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -1,5 +1,18 @@
 mod renderer;
 mod texture;
+mod capture;
+mod shader_watcher;
+mod material;
+mod post_process;
+mod lighting;
+mod bitmap_font;
+mod debug_draw;
+mod frame_pacer;
+mod stats;
+mod render_graph;
+mod spatial_hash;
+mod random;
+mod ui;
 mod game;
 mod components;
 mod systems;
@@ -13,6 +26,7 @@ use winit::{
 };
 
 const DESIRED_RENDER_SIZE: [u32; 2] = [1280_u32, 720_u32];
+const RECORDING_MAX_FRAMES: u32 = 600;
 
 
 fn main() {
@@ -27,23 +41,68 @@ fn main() {
         .build(&event_loop)
         .unwrap();
     
-    let mut renderer = futures::executor::block_on(Renderer::new(&window, winit::dpi::PhysicalSize::new(DESIRED_RENDER_SIZE[0], DESIRED_RENDER_SIZE[1])));
+    // VSYNC=off|mailbox and TARGET_FPS=<fps> for checking how the game holds up at other frame rates,
+    // MSAA=4 and RENDER_SCALE=<0.1 to 1> for smoothing edges or going easy on slow machines
+    let renderer_config = RendererConfig {
+        vsync: match std::env::var("VSYNC").as_deref() {
+            Ok("off") => VSync::Off,
+            Ok("mailbox") => VSync::Mailbox,
+            _ => VSync::On,
+        },
+        target_fps: std::env::var("TARGET_FPS").ok().and_then(|target_fps| target_fps.parse().ok()),
+        msaa_samples: std::env::var("MSAA").ok().and_then(|msaa_samples| msaa_samples.parse().ok()).unwrap_or(1),
+        render_scale: std::env::var("RENDER_SCALE").ok().and_then(|render_scale| render_scale.parse().ok()).unwrap_or(1.),
+        ..Default::default()
+    };
+    let mut renderer = match futures::executor::block_on(Renderer::new(&window, winit::dpi::PhysicalSize::new(DESIRED_RENDER_SIZE[0], DESIRED_RENDER_SIZE[1]), &renderer_config)) {
+        Ok(renderer) => renderer,
+        Err(error) => {
+            println!("{}", error);
+            return;
+        },
+    };
+    // Set SHADER_HOT_RELOAD to tweak the sprite shaders in src/ without rebuilding
+    if std::env::var("SHADER_HOT_RELOAD").is_ok() {
+        renderer.watch_shaders("src/shader.vert", "src/shader.frag");
+    }
     let mut game = Game::new(DESIRED_RENDER_SIZE);
-    game.init(&mut renderer);
+    // BENCHMARK=<sprite count> starts in the sprite benchmark instead, 50k sprites if the count is left out.
+    // PHYSICS_BENCHMARK=<tile counts, comma separated> goes through the physics benchmark with each of them and
+    // prints the physics time per step for every one, 1k to 16k tiles if they're left out
+    if let Ok(sprite_count) = std::env::var("BENCHMARK") {
+        game.init_benchmark(&mut renderer, sprite_count.parse().unwrap_or(50_000));
+    } else if let Ok(tile_counts) = std::env::var("PHYSICS_BENCHMARK") {
+        let tile_counts = tile_counts.split(',').filter_map(|tile_count| tile_count.trim().parse().ok()).collect();
+        game.init_physics_benchmark(&mut renderer, tile_counts);
+    } else {
+        game.init(&mut renderer);
+    }
     
-    let mut last_time = std::time::Instant::now();
+    // RECORDING=apng makes F11 record an animated PNG instead of a GIF
+    let record_apng = std::env::var("RECORDING").as_deref() == Ok("apng");
+    let mut show_physics_debug = false;
+    let mut show_stats = false;
+    let mut hide_ui = false;
 
     event_loop.run(move |event, _, control_flow| {
         match event {
             Event::RedrawRequested(_) => {
-                let time_passed = last_time.elapsed().as_millis();
-                last_time = std::time::Instant::now();
+                let time_passed = renderer.begin_frame();
 
                 if game.update(time_passed, &mut renderer) {
                     *control_flow = ControlFlow::Exit;
                 }
-                let (renderables, renderable_texts) = &game.get_renderables();
-                renderer.render(renderables, renderable_texts);
+                if show_physics_debug {
+                    game.draw_physics_debug(renderer.debug());
+                }
+                if show_stats {
+                    game.draw_stats_overlay(renderer.debug());
+                }
+                let (renderables, renderable_particles, renderable_texts) = &game.get_renderables();
+                if let Err(error) = renderer.render(renderables, renderable_particles, renderable_texts, &game.get_lighting()) {
+                    println!("{}", error);
+                    *control_flow = ControlFlow::Exit;
+                }
             }
             Event::MainEventsCleared => {
                 window.request_redraw();
@@ -70,6 +129,88 @@ fn main() {
                                     virtual_keycode: Some(VirtualKeyCode::Escape),
                                     ..
                                 } => *control_flow = ControlFlow::Exit,
+                                KeyboardInput {
+                                    state: ElementState::Pressed, 
+                                    virtual_keycode: Some(VirtualKeyCode::F1),
+                                    ..
+                                } => show_physics_debug = !show_physics_debug,
+                                KeyboardInput {
+                                    state: ElementState::Pressed, 
+                                    virtual_keycode: Some(VirtualKeyCode::F3),
+                                    ..
+                                } => show_stats = !show_stats,
+                                // Drops the UI pass for screenshots without the HUD in them
+                                KeyboardInput {
+                                    state: ElementState::Pressed, 
+                                    virtual_keycode: Some(VirtualKeyCode::F2),
+                                    ..
+                                } => {
+                                    hide_ui = !hide_ui;
+                                    renderer.render_graph().set_enabled("ui", !hide_ui);
+                                },
+                                KeyboardInput {
+                                    state: ElementState::Pressed, 
+                                    virtual_keycode: Some(VirtualKeyCode::F4),
+                                    ..
+                                } => {
+                                    // Full, half and quarter resolution
+                                    let render_scale = if renderer.render_scale() > 0.5 { 0.5 } else if renderer.render_scale() > 0.25 { 0.25 } else { 1. };
+                                    renderer.set_render_scale(render_scale);
+                                },
+                                KeyboardInput {
+                                    state: ElementState::Pressed, 
+                                    virtual_keycode: Some(VirtualKeyCode::F5),
+                                    ..
+                                } => renderer.toggle_post_effect(PostEffect::Crt),
+                                KeyboardInput {
+                                    state: ElementState::Pressed, 
+                                    virtual_keycode: Some(VirtualKeyCode::F6),
+                                    ..
+                                } => renderer.toggle_post_effect(PostEffect::Vignette),
+                                KeyboardInput {
+                                    state: ElementState::Pressed, 
+                                    virtual_keycode: Some(VirtualKeyCode::F7),
+                                    ..
+                                } => renderer.toggle_post_effect(PostEffect::ColorGrading),
+                                KeyboardInput {
+                                    state: ElementState::Pressed, 
+                                    virtual_keycode: Some(VirtualKeyCode::F8),
+                                    ..
+                                } => renderer.toggle_post_effect(PostEffect::Bloom),
+                                KeyboardInput {
+                                    state: ElementState::Pressed, 
+                                    virtual_keycode: Some(VirtualKeyCode::F9),
+                                    ..
+                                } => renderer.toggle_post_effect(PostEffect::Pixelate),
+                                KeyboardInput {
+                                    state: ElementState::Pressed, 
+                                    virtual_keycode: Some(VirtualKeyCode::F12),
+                                    ..
+                                } => renderer.take_screenshot(&format!("screenshot_{}.png", timestamp())),
+                                KeyboardInput {
+                                    state: ElementState::Pressed, 
+                                    virtual_keycode: Some(VirtualKeyCode::F11),
+                                    ..
+                                } => {
+                                    if renderer.is_recording() {
+                                        renderer.stop_recording();
+                                    } else if record_apng {
+                                        renderer.start_recording(RecordingMode::Apng {count: RECORDING_MAX_FRAMES, path: format!("recording_{}.png", timestamp())});
+                                    } else {
+                                        renderer.start_recording(RecordingMode::Gif {count: RECORDING_MAX_FRAMES, path: format!("recording_{}.gif", timestamp())});
+                                    }
+                                },
+                                KeyboardInput {
+                                    state: ElementState::Pressed, 
+                                    virtual_keycode: Some(VirtualKeyCode::F10),
+                                    ..
+                                } => {
+                                    if renderer.is_recording() {
+                                        renderer.stop_recording();
+                                    } else {
+                                        renderer.start_recording(RecordingMode::Frames {count: RECORDING_MAX_FRAMES, directory: format!("frames_{}", timestamp())});
+                                    }
+                                },
                                 _ => {}
                             }
                         }
@@ -82,3 +223,9 @@ fn main() {
     });
 }
 
+fn timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,81 @@
+use wgpu::util::DeviceExt;
+use crate::renderer::SpriteTarget;
+
+// What material shaders get at set 1, binding 0 - laid out like the uniform block in the shaders
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct MaterialUniforms {
+    params: [f32; 4],
+    time: f32,
+    _padding: [f32; 3],
+}
+unsafe impl bytemuck::Pod for MaterialUniforms{}
+unsafe impl bytemuck::Zeroable for MaterialUniforms{}
+
+// A sprite pipeline with its own fragment shader. The params are shared by every sprite using the material,
+// anything that differs per sprite goes through the sprite's material_params instead
+pub struct Material {
+    pub name: String,
+    pub pipeline: wgpu::RenderPipeline,
+    pub frame_pipeline: wgpu::RenderPipeline, // For sprites drawn straight into the frame
+    pub bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+    params: [f32; 4],
+}
+
+impl Material {
+    pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("material_bind_group_layout"),
+        })
+    }
+
+    pub fn new(device: &wgpu::Device, name: &str, pipeline: wgpu::RenderPipeline, frame_pipeline: wgpu::RenderPipeline, layout: &wgpu::BindGroupLayout, params: [f32; 4]) -> Self {
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(name),
+            contents: bytemuck::cast_slice(&[MaterialUniforms { params, time: 0., _padding: [0.; 3] }]),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some(name),
+        });
+
+        Self { name: name.to_owned(), pipeline, frame_pipeline, bind_group, uniform_buffer, params }
+    }
+
+    pub fn pipeline(&self, target: SpriteTarget) -> &wgpu::RenderPipeline {
+        match target {
+            SpriteTarget::Scene => &self.pipeline,
+            SpriteTarget::Frame => &self.frame_pipeline,
+        }
+    }
+
+    pub fn set_params(&mut self, params: [f32; 4]) {
+        self.params = params;
+    }
+
+    // Time is in seconds since the renderer started, for materials that animate on their own
+    pub fn write_uniforms(&self, queue: &wgpu::Queue, time: f32) {
+        let uniforms = MaterialUniforms { params: self.params, time, _padding: [0.; 3] };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+    }
+}
@@ -0,0 +1,23 @@
+#version 450
+
+layout(location=0) in vec3 v_position;
+layout(location=1) in vec2 v_tex_coords;
+layout(location=2) in vec4 v_color;
+layout(location=3) in vec4 v_params; // x: how white, 0 to 1
+
+layout(set = 0, binding = 0) uniform texture2D t_diffuse;
+layout(set = 0, binding = 1) uniform sampler s_diffuse;
+
+layout(set = 1, binding = 0) uniform MaterialUniforms {
+    vec4 u_params; // rgb: flash color
+    float u_time;
+};
+
+layout(location=0) out vec4 f_color;
+layout(location=1) out vec4 f_normal; // Materials are lit as if flat
+
+void main() {
+    vec4 object_color = texture(sampler2D(t_diffuse, s_diffuse), v_tex_coords) * v_color;
+    f_color = vec4(mix(object_color.rgb, u_params.rgb, clamp(v_params.x, 0.0, 1.0)), object_color.a);
+    f_normal = vec4(0.5, 0.5, 1.0, f_color.a);
+}
\ No newline at end of file
@@ -0,0 +1,34 @@
+#version 450
+
+layout(location=0) in vec3 v_position;
+layout(location=1) in vec2 v_tex_coords;
+layout(location=2) in vec4 v_color;
+layout(location=3) in vec4 v_params; // x: outline strength, 0 to 1
+
+layout(set = 0, binding = 0) uniform texture2D t_diffuse;
+layout(set = 0, binding = 1) uniform sampler s_diffuse;
+
+layout(set = 1, binding = 0) uniform MaterialUniforms {
+    vec4 u_params; // rgb: outline color, a: thickness in texels
+    float u_time;
+};
+
+layout(location=0) out vec4 f_color;
+layout(location=1) out vec4 f_normal; // Materials are lit as if flat
+
+void main() {
+    vec4 object_color = texture(sampler2D(t_diffuse, s_diffuse), v_tex_coords) * v_color;
+    vec2 texel = u_params.a / vec2(textureSize(sampler2D(t_diffuse, s_diffuse), 0));
+    float neighbours = texture(sampler2D(t_diffuse, s_diffuse), v_tex_coords + vec2(texel.x, 0.0)).a
+        + texture(sampler2D(t_diffuse, s_diffuse), v_tex_coords - vec2(texel.x, 0.0)).a
+        + texture(sampler2D(t_diffuse, s_diffuse), v_tex_coords + vec2(0.0, texel.y)).a
+        + texture(sampler2D(t_diffuse, s_diffuse), v_tex_coords - vec2(0.0, texel.y)).a;
+    if (object_color.a < 0.5 && neighbours > 0.0) {
+        // Pulse a little so collectibles catch the eye
+        float pulse = 0.75 + 0.25 * sin(u_time * 4.0);
+        f_color = vec4(u_params.rgb, clamp(v_params.x, 0.0, 1.0) * pulse * v_color.a);
+    } else {
+        f_color = object_color;
+    }
+    f_normal = vec4(0.5, 0.5, 1.0, f_color.a);
+}
\ No newline at end of file
@@ -0,0 +1,21 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+
+layout(set = 0, binding = 0) uniform texture2D t_source;
+layout(set = 0, binding = 1) uniform sampler s_source;
+layout(set = 0, binding = 2) uniform PostUniforms {
+    vec2 u_resolution; // Of the source, in pixels
+    float u_time;
+    float u_intensity;
+    vec4 u_params;
+};
+layout(set = 0, binding = 3) uniform texture2D t_bloom;
+
+layout(location=0) out vec4 f_color;
+
+void main() {
+    vec4 color = texture(sampler2D(t_source, s_source), v_tex_coords);
+    vec3 bloom = texture(sampler2D(t_bloom, s_source), v_tex_coords).rgb;
+    f_color = vec4(color.rgb + bloom * u_intensity, color.a);
+}
\ No newline at end of file
@@ -0,0 +1,21 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+
+layout(set = 0, binding = 0) uniform texture2D t_source;
+layout(set = 0, binding = 1) uniform sampler s_source;
+layout(set = 0, binding = 2) uniform PostUniforms {
+    vec2 u_resolution; // Of the source, in pixels
+    float u_time;
+    float u_intensity;
+    vec4 u_params;
+};
+
+layout(location=0) out vec4 f_color;
+
+// u_params.x: how bright a pixel has to be to glow
+void main() {
+    vec3 color = texture(sampler2D(t_source, s_source), v_tex_coords).rgb;
+    float brightness = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    f_color = vec4(color * smoothstep(u_params.x, u_params.x + 0.1, brightness), 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,26 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+
+layout(set = 0, binding = 0) uniform texture2D t_source;
+layout(set = 0, binding = 1) uniform sampler s_source;
+layout(set = 0, binding = 2) uniform PostUniforms {
+    vec2 u_resolution; // Of the source, in pixels
+    float u_time;
+    float u_intensity;
+    vec4 u_params;
+};
+
+layout(location=0) out vec4 f_color;
+
+// u_params.xy: blur direction, one pass horizontally and one vertically
+void main() {
+    float weights[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+    vec2 texel = u_params.xy / u_resolution;
+    vec3 color = texture(sampler2D(t_source, s_source), v_tex_coords).rgb * weights[0];
+    for (int i = 1; i < 5; i++) {
+        color += texture(sampler2D(t_source, s_source), v_tex_coords + texel * float(i)).rgb * weights[i];
+        color += texture(sampler2D(t_source, s_source), v_tex_coords - texel * float(i)).rgb * weights[i];
+    }
+    f_color = vec4(color, 1.0);
+}
\ No newline at end of file
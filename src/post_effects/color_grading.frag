@@ -0,0 +1,34 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+
+layout(set = 0, binding = 0) uniform texture2D t_source;
+layout(set = 0, binding = 1) uniform sampler s_source;
+layout(set = 0, binding = 2) uniform PostUniforms {
+    vec2 u_resolution; // Of the source, in pixels
+    float u_time;
+    float u_intensity;
+    vec4 u_params;
+};
+layout(set = 0, binding = 3) uniform texture2D t_lut;
+
+layout(location=0) out vec4 f_color;
+
+// The LUT is a 256x16 strip of 16 slices, blue picks the slice, red and green the texel inside it.
+// It's authored in sRGB while the scene comes in linear, hence the conversions around the lookup
+vec3 lookup(vec3 color) {
+    float size = 16.0;
+    float blue = color.b * (size - 1.0);
+    float x = (color.r * (size - 1.0) + 0.5) / (size * size);
+    float y = (color.g * (size - 1.0) + 0.5) / size;
+    vec3 low = texture(sampler2D(t_lut, s_source), vec2(x + floor(blue) / size, y)).rgb;
+    vec3 high = texture(sampler2D(t_lut, s_source), vec2(x + ceil(blue) / size, y)).rgb;
+    return mix(low, high, fract(blue));
+}
+
+void main() {
+    vec4 color = texture(sampler2D(t_source, s_source), v_tex_coords);
+    vec3 srgb = pow(clamp(color.rgb, 0.0, 1.0), vec3(1.0 / 2.2));
+    vec3 graded = pow(lookup(srgb), vec3(2.2));
+    f_color = vec4(mix(color.rgb, graded, u_intensity), color.a);
+}
\ No newline at end of file
@@ -0,0 +1,38 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+
+layout(set = 0, binding = 0) uniform texture2D t_source;
+layout(set = 0, binding = 1) uniform sampler s_source;
+layout(set = 0, binding = 2) uniform PostUniforms {
+    vec2 u_resolution; // Of the source, in pixels
+    float u_time;
+    float u_intensity;
+    vec4 u_params;
+};
+
+layout(location=0) out vec4 f_color;
+
+// u_params.x: screen curvature, u_params.y: scanline count
+void main() {
+    // Bulge the picture like an old tube
+    vec2 centered = v_tex_coords * 2.0 - 1.0;
+    centered *= 1.0 + u_params.x * u_intensity * dot(centered, centered);
+    vec2 uv = centered * 0.5 + 0.5;
+    if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0) {
+        f_color = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    // A little color fringing towards the edges
+    vec2 shift = centered * 1.5 / u_resolution;
+    vec3 color = vec3(
+        texture(sampler2D(t_source, s_source), uv + shift).r,
+        texture(sampler2D(t_source, s_source), uv).g,
+        texture(sampler2D(t_source, s_source), uv - shift).b
+    );
+
+    float scanline = 0.5 + 0.5 * sin(uv.y * u_params.y * 6.2831853 + u_time * 2.0);
+    color *= mix(1.0, 0.75 + 0.25 * scanline, u_intensity);
+    f_color = vec4(color, 1.0);
+}
\ No newline at end of file
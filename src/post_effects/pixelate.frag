@@ -0,0 +1,21 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+
+layout(set = 0, binding = 0) uniform texture2D t_source;
+layout(set = 0, binding = 1) uniform sampler s_source;
+layout(set = 0, binding = 2) uniform PostUniforms {
+    vec2 u_resolution; // Of the source, in pixels
+    float u_time;
+    float u_intensity;
+    vec4 u_params;
+};
+
+layout(location=0) out vec4 f_color;
+
+// u_params.x: block size in pixels
+void main() {
+    vec2 block = max(u_params.x * u_intensity, 1.0) / u_resolution;
+    vec2 uv = (floor(v_tex_coords / block) + 0.5) * block;
+    f_color = texture(sampler2D(t_source, s_source), uv);
+}
\ No newline at end of file
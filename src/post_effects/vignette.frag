@@ -0,0 +1,23 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+
+layout(set = 0, binding = 0) uniform texture2D t_source;
+layout(set = 0, binding = 1) uniform sampler s_source;
+layout(set = 0, binding = 2) uniform PostUniforms {
+    vec2 u_resolution; // Of the source, in pixels
+    float u_time;
+    float u_intensity;
+    vec4 u_params;
+};
+
+layout(location=0) out vec4 f_color;
+
+// u_params.x: where the darkening starts, u_params.y: where it's at its darkest (distance from the center)
+void main() {
+    vec4 color = texture(sampler2D(t_source, s_source), v_tex_coords);
+    vec2 centered = v_tex_coords - 0.5;
+    centered.x *= u_resolution.x / u_resolution.y;
+    float vignette = smoothstep(u_params.x, u_params.y, length(centered));
+    f_color = vec4(color.rgb * (1.0 - vignette * u_intensity), color.a);
+}
\ No newline at end of file
@@ -0,0 +1,505 @@
+use anyhow::{Context, Result};
+use image::GenericImageView;
+use core::num::NonZeroU32;
+use crate::stats::RenderStats;
+
+// The bloom is blurred at a fraction of the screen size, it's cheaper and wider that way
+const BLOOM_DOWNSCALE: u32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PostEffect {
+    Pixelate,
+    Bloom,
+    ColorGrading,
+    Vignette,
+    Crt,
+}
+
+pub struct PostEffectSettings {
+    pub enabled: bool,
+    pub intensity: f32, // 0 leaves the picture alone
+    pub params: [f32; 4], // Effect specific, see the comments in src/post_effects/
+}
+
+// Same layout as the uniform block in src/post_effects/
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct PostUniforms {
+    resolution: [f32; 2],
+    time: f32,
+    intensity: f32,
+    params: [f32; 4],
+}
+unsafe impl bytemuck::Pod for PostUniforms{}
+unsafe impl bytemuck::Zeroable for PostUniforms{}
+
+// Screen sized texture that gets drawn into and then sampled by the next pass
+pub struct RenderTarget {
+    pub view: wgpu::TextureView,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RenderTarget {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, format: wgpu::TextureFormat, label: &str) -> Self {
+        Self::new_multisampled(device, width, height, format, 1, label)
+    }
+
+    // Multisampled targets can't be sampled like the others, they only get drawn into and resolved
+    pub fn new_multisampled(device: &wgpu::Device, width: u32, height: u32, format: wgpu::TextureFormat, sample_count: u32, label: &str) -> Self {
+        let usage = if sample_count > 1 {
+            wgpu::TextureUsage::RENDER_ATTACHMENT
+        } else {
+            wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self { view, width, height }
+    }
+}
+
+// One fullscreen pass with a uniform buffer of its own, and a bind group for every texture it can read from.
+// Every pass needs its own buffer, queued writes would all land before the first pass runs
+struct Pass {
+    uniform_buffer: wgpu::Buffer,
+    bind_groups: Vec<wgpu::BindGroup>, // Made by create_bind_groups, for each scene target or the one texture it reads
+}
+
+impl Pass {
+    fn new(device: &wgpu::Device) -> Self {
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("post_process_uniforms"),
+            size: std::mem::size_of::<PostUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self { uniform_buffer, bind_groups: Vec::new() }
+    }
+}
+
+struct Effect {
+    kind: PostEffect,
+    settings: PostEffectSettings,
+    pipeline: wgpu::RenderPipeline,
+    pass: Pass,
+}
+
+// The world gets drawn into an offscreen texture first, then every enabled effect runs over the whole screen,
+// ping-ponging between two textures with the last one writing straight into the frame
+pub struct PostProcessor {
+    format: wgpu::TextureFormat,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    upscale_sampler: wgpu::Sampler, // Nearest, so a lowered render scale gives big crisp pixels instead of a blur
+    copy_pipeline: wgpu::RenderPipeline,
+    bloom_extract_pipeline: wgpu::RenderPipeline,
+    blur_pipeline: wgpu::RenderPipeline,
+    copy_pass: Pass,
+    bloom_extract_pass: Pass,
+    blur_passes: [Pass; 2], // Horizontal from the first bloom target, vertical from the second
+    blit_pass: Pass,
+    blit_source: Option<u64>, // Id of the capture target blit_pass has a bind group for
+    effects: Vec<Effect>, // Applied in this order
+    scene_targets: Vec<RenderTarget>,
+    bloom_targets: Vec<RenderTarget>,
+    lut_view: wgpu::TextureView,
+}
+
+impl PostProcessor {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat) -> Result<Self> {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float {filterable: true,},
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Second input for the effects that need one - the LUT or the blurred bloom
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float {filterable: true,},
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("post_process_bind_group_layout"),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                &bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let upscale_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let vs_module = device.create_shader_module(&wgpu::include_spirv!("post_effects/fullscreen.vert.spv"));
+
+        let create_pipeline = |fragment_shader: wgpu::ShaderModuleDescriptor| {
+            let fs_module = device.create_shader_module(&fragment_shader);
+            create_post_pipeline(device, &pipeline_layout, &vs_module, &fs_module, format)
+        };
+        let copy_pipeline = create_pipeline(wgpu::include_spirv!("post_effects/copy.frag.spv"));
+        let bloom_extract_pipeline = create_pipeline(wgpu::include_spirv!("post_effects/bloom_extract.frag.spv"));
+        let blur_pipeline = create_pipeline(wgpu::include_spirv!("post_effects/blur.frag.spv"));
+        let effects = vec![
+            Effect {
+                kind: PostEffect::Pixelate,
+                settings: PostEffectSettings { enabled: false, intensity: 1., params: [4., 0., 0., 0.] },
+                pipeline: create_pipeline(wgpu::include_spirv!("post_effects/pixelate.frag.spv")),
+                pass: Pass::new(device),
+            },
+            Effect {
+                kind: PostEffect::Bloom,
+                settings: PostEffectSettings { enabled: false, intensity: 0.8, params: [0.7, 0., 0., 0.] },
+                pipeline: create_pipeline(wgpu::include_spirv!("post_effects/bloom_combine.frag.spv")),
+                pass: Pass::new(device),
+            },
+            Effect {
+                kind: PostEffect::ColorGrading,
+                settings: PostEffectSettings { enabled: false, intensity: 1., params: [0., 0., 0., 0.] },
+                pipeline: create_pipeline(wgpu::include_spirv!("post_effects/color_grading.frag.spv")),
+                pass: Pass::new(device),
+            },
+            Effect {
+                kind: PostEffect::Vignette,
+                settings: PostEffectSettings { enabled: false, intensity: 0.6, params: [0.35, 0.9, 0., 0.] },
+                pipeline: create_pipeline(wgpu::include_spirv!("post_effects/vignette.frag.spv")),
+                pass: Pass::new(device),
+            },
+            Effect {
+                kind: PostEffect::Crt,
+                settings: PostEffectSettings { enabled: false, intensity: 1., params: [0.08, 360., 0., 0.] },
+                pipeline: create_pipeline(wgpu::include_spirv!("post_effects/crt.frag.spv")),
+                pass: Pass::new(device),
+            },
+        ];
+
+        let lut = image::load_from_memory(include_bytes!("../res/lut/warm.png")).context("Loading default LUT")?;
+        let lut_view = create_lut_view(device, queue, &lut);
+
+        Ok(Self {
+            format,
+            bind_group_layout,
+            sampler,
+            upscale_sampler,
+            copy_pipeline,
+            bloom_extract_pipeline,
+            blur_pipeline,
+            copy_pass: Pass::new(device),
+            bloom_extract_pass: Pass::new(device),
+            blur_passes: [Pass::new(device), Pass::new(device)],
+            blit_pass: Pass::new(device),
+            blit_source: None,
+            effects,
+            scene_targets: vec![],
+            bloom_targets: vec![],
+            lut_view,
+        })
+    }
+
+    pub fn settings_mut(&mut self, effect: PostEffect) -> &mut PostEffectSettings {
+        &mut self.effects.iter_mut().find(|e| e.kind == effect).unwrap().settings
+    }
+
+    // 256x16 strip of 16 slices along blue, red and green within a slice
+    pub fn set_lut(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, path: &str) -> Result<()> {
+        let lut = image::open(path).with_context(|| format!("Loading LUT {}", path))?;
+        if lut.dimensions() != (256, 16) {
+            anyhow::bail!("LUT {} has to be 256x16, not {:?}", path, lut.dimensions());
+        }
+        self.lut_view = create_lut_view(device, queue, &lut);
+        if !self.scene_targets.is_empty() {
+            self.create_bind_groups(device);
+        }
+        Ok(())
+    }
+
+    // (Re)creates the offscreen textures when the frame size changes
+    pub fn prepare(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        if let Some(target) = self.scene_targets.first() {
+            if target.width == width && target.height == height {
+                return;
+            }
+        }
+        self.scene_targets = vec![
+            RenderTarget::new(device, width, height, self.format, "post_process_scene_0"),
+            RenderTarget::new(device, width, height, self.format, "post_process_scene_1"),
+        ];
+        let (bloom_width, bloom_height) = ((width / BLOOM_DOWNSCALE).max(1), (height / BLOOM_DOWNSCALE).max(1));
+        self.bloom_targets = vec![
+            RenderTarget::new(device, bloom_width, bloom_height, self.format, "post_process_bloom_0"),
+            RenderTarget::new(device, bloom_width, bloom_height, self.format, "post_process_bloom_1"),
+        ];
+        self.create_bind_groups(device);
+    }
+
+    // The bind groups point at the targets, so they get made again along with them
+    fn create_bind_groups(&mut self, device: &wgpu::Device) {
+        let layout = &self.bind_group_layout;
+        let (sampler, upscale_sampler) = (&self.sampler, &self.upscale_sampler);
+        let scene = &self.scene_targets;
+        let bloom = &self.bloom_targets;
+        let lut_view = &self.lut_view;
+        let for_every_scene_target = |pass: &Pass, sampler: &wgpu::Sampler, extra: Option<&wgpu::TextureView>| -> Vec<wgpu::BindGroup> {
+            scene.iter().map(|source| create_bind_group(device, layout, pass, sampler, &source.view, extra)).collect()
+        };
+        for effect in self.effects.iter_mut() {
+            let extra = match effect.kind {
+                PostEffect::Bloom => Some(&bloom[0].view),
+                PostEffect::ColorGrading => Some(lut_view),
+                _ => None,
+            };
+            effect.pass.bind_groups = for_every_scene_target(&effect.pass, sampler, extra);
+        }
+        self.bloom_extract_pass.bind_groups = for_every_scene_target(&self.bloom_extract_pass, sampler, None);
+        self.copy_pass.bind_groups = for_every_scene_target(&self.copy_pass, upscale_sampler, None);
+        for (blur_pass, source) in self.blur_passes.iter_mut().zip(bloom.iter()) {
+            blur_pass.bind_groups = vec![create_bind_group(device, layout, blur_pass, sampler, &source.view, None)];
+        }
+    }
+
+    // Where the world should be drawn, call prepare first
+    pub fn scene_view(&self) -> &wgpu::TextureView {
+        &self.scene_targets[0].view
+    }
+
+    // Runs the enabled effects over the scene and writes the result into output. Effects run at the size the targets
+    // were prepared with, when output_size differs the result gets stretched over the output at the end
+    pub fn apply(&self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView, output_size: [u32; 2], time: f32, stats: &mut RenderStats) {
+        let enabled: Vec<&Effect> = self.effects.iter().filter(|effect| effect.settings.enabled).collect();
+        let uniforms = |target: &RenderTarget, settings: &PostEffectSettings| PostUniforms {
+            resolution: [target.width as f32, target.height as f32],
+            time,
+            intensity: settings.intensity,
+            params: settings.params,
+        };
+
+        let upscale = [self.scene_targets[0].width, self.scene_targets[0].height] != output_size;
+
+        let mut source = 0;
+        for (i, effect) in enabled.iter().enumerate() {
+            let destination = if i == enabled.len() - 1 && !upscale { output } else { &self.scene_targets[1 - source].view };
+            let source_target = &self.scene_targets[source];
+            if effect.kind == PostEffect::Bloom {
+                // Bright parts go into the small targets, get blurred there and added back on top of the scene
+                let bloom = &self.bloom_targets;
+                let horizontal = PostEffectSettings { params: [1., 0., 0., 0.], ..effect.settings };
+                let vertical = PostEffectSettings { params: [0., 1., 0., 0.], ..effect.settings };
+                self.run_pass(queue, encoder, &self.bloom_extract_pipeline, &self.bloom_extract_pass, source, &bloom[0].view, uniforms(source_target, &effect.settings), stats);
+                self.run_pass(queue, encoder, &self.blur_pipeline, &self.blur_passes[0], 0, &bloom[1].view, uniforms(&bloom[0], &horizontal), stats);
+                self.run_pass(queue, encoder, &self.blur_pipeline, &self.blur_passes[1], 0, &bloom[0].view, uniforms(&bloom[1], &vertical), stats);
+            }
+            // The bloom and the LUT are already in the bind groups of the effects that read them
+            self.run_pass(queue, encoder, &effect.pipeline, &effect.pass, source, destination, uniforms(source_target, &effect.settings), stats);
+            source = 1 - source;
+        }
+
+        // Nothing has written the output yet
+        if enabled.is_empty() || upscale {
+            let settings = PostEffectSettings { enabled: true, intensity: 1., params: [0.; 4] };
+            let source_target = &self.scene_targets[source];
+            self.run_pass(queue, encoder, &self.copy_pipeline, &self.copy_pass, source, output, uniforms(source_target, &settings), stats);
+        }
+    }
+
+    // Copies source onto output as it is, for a frame that got drawn somewhere else first. source_id tells apart the
+    // textures it gets, the bind group is only made again when it changes
+    pub fn blit(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, source: &wgpu::TextureView, source_id: u64, output: &wgpu::TextureView, size: [u32; 2], stats: &mut RenderStats) {
+        if self.blit_source != Some(source_id) {
+            self.blit_pass.bind_groups = vec![create_bind_group(device, &self.bind_group_layout, &self.blit_pass, &self.upscale_sampler, source, None)];
+            self.blit_source = Some(source_id);
+        }
+        let uniforms = PostUniforms { resolution: [size[0] as f32, size[1] as f32], time: 0., intensity: 1., params: [0.; 4] };
+        self.run_pass(queue, encoder, &self.copy_pipeline, &self.blit_pass, 0, output, uniforms, stats);
+    }
+
+    fn run_pass(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        pass: &Pass,
+        bind_group: usize,
+        destination: &wgpu::TextureView,
+        uniforms: PostUniforms,
+        stats: &mut RenderStats,
+    ) {
+        stats.fullscreen_pass();
+        queue.write_buffer(&pass.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[
+                wgpu::RenderPassColorAttachment {
+                    view: destination,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    }
+                }
+            ],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &pass.bind_groups[bind_group], &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+// Source goes to binding 0, extra (the LUT or the blurred bloom) to binding 3 when the pass has one
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    pass: &Pass,
+    sampler: &wgpu::Sampler,
+    source: &wgpu::TextureView,
+    extra: Option<&wgpu::TextureView>,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(source),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: pass.uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(extra.unwrap_or(source)),
+            },
+        ],
+        label: None,
+    })
+}
+
+fn create_post_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    vs_module: &wgpu::ShaderModule,
+    fs_module: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: vs_module,
+            entry_point: "main",
+            buffers: &[],
+        },
+        depth_stencil: None,
+        fragment: Some(wgpu::FragmentState {
+            module: fs_module,
+            entry_point: "main",
+            targets: &[
+                wgpu::ColorTargetState {
+                    format,
+                    write_mask: wgpu::ColorWrite::ALL,
+                    blend: None,
+                }
+            ]
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        primitive: wgpu::PrimitiveState::default(),
+    })
+}
+
+// LUTs hold colors already, they mustn't be treated as sRGB
+fn create_lut_view(device: &wgpu::Device, queue: &wgpu::Queue, img: &image::DynamicImage) -> wgpu::TextureView {
+    let rgba = img.to_rgba8();
+    let (width, height) = img.dimensions();
+    let size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("color_grading_lut"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+    });
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        &rgba,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: NonZeroU32::new(4 * width),
+            rows_per_image: NonZeroU32::new(height),
+        },
+        size,
+    );
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
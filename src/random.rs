@@ -0,0 +1,14 @@
+// Xorshift, good enough for sparkles and benchmark scenes and doesn't need another dependency.
+// The state must not be 0, it would stay 0 forever
+pub fn random(state: &mut u32) -> f32 {
+    let mut x = *state;
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    *state = x;
+    (x >> 8) as f32 / (1u32 << 24) as f32
+}
+
+pub fn random_in_range(state: &mut u32, range: [f32; 2]) -> f32 {
+    range[0] + (range[1] - range[0]) * random(state)
+}
@@ -0,0 +1,312 @@
+use anyhow::{anyhow, Result};
+
+// What the renderer knows how to run. A pass can show up in the graph more than once under different names
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PassKind {
+    World, // Sprites, particles and everything else below the UI layer
+    Ui, // UI layer sprites and bitmap font text
+    Lighting,
+    PostProcess,
+    Debug,
+    Text, // Vector font text, straight into the frame
+}
+
+impl PassKind {
+    // Sprite passes draw into the frame when they write it and into the scene otherwise, the rest always draw
+    // into the same place whatever they declare
+    fn fixed_writes(self) -> Option<&'static [Attachment]> {
+        match self {
+            PassKind::World | PassKind::Ui => None,
+            PassKind::Lighting => Some(&[Attachment::Scene]),
+            PassKind::PostProcess | PassKind::Debug | PassKind::Text => Some(&[Attachment::Frame]),
+        }
+    }
+}
+
+// Textures the passes hand to each other
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Attachment {
+    Albedo, // World colors before lighting, the same texture as Scene when there's no lighting pass
+    Normals,
+    Scene, // What the post effects start from, scaled by the render scale
+    Frame, // Swap chain or capture target
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GraphPass {
+    pub name: String,
+    pub kind: PassKind,
+    pub reads: Vec<Attachment>,
+    pub writes: Vec<Attachment>,
+    pub after: Vec<String>, // Passes that have to run first even though they share no attachments
+    pub enabled: bool,
+}
+
+impl GraphPass {
+    pub fn new(name: &str, kind: PassKind, reads: &[Attachment], writes: &[Attachment]) -> Self {
+        Self {
+            name: name.to_owned(),
+            kind,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+            after: Vec::new(),
+            enabled: true,
+        }
+    }
+}
+
+// Passes in the order they were declared, run in an order that puts every pass after the ones writing what it
+// reads. Writers only count when they were declared earlier, so passes that draw on top of an attachment
+// (reading and writing it) stay where they were put
+pub struct RenderGraph {
+    passes: Vec<GraphPass>,
+    order: Vec<GraphPass>, // The last one that compiled
+    changed: bool, // Since order was compiled
+    warnings: Vec<String>, // Of the last compile, so each one only gets printed when it shows up
+}
+
+impl Default for RenderGraph {
+    // What the renderer has always done
+    fn default() -> Self {
+        let mut graph = Self { passes: Vec::new(), order: Vec::new(), changed: true, warnings: Vec::new() };
+        graph.add_pass(GraphPass::new("world", PassKind::World, &[], &[Attachment::Albedo, Attachment::Normals]));
+        graph.add_pass(GraphPass::new("lighting", PassKind::Lighting, &[Attachment::Albedo, Attachment::Normals], &[Attachment::Scene]));
+        graph.add_pass(GraphPass::new("post_process", PassKind::PostProcess, &[Attachment::Scene], &[Attachment::Frame]));
+        // UI goes over the post effects, with its own depth, like the text on it
+        graph.add_pass(GraphPass::new("ui", PassKind::Ui, &[Attachment::Frame], &[Attachment::Frame]));
+        // Debug shapes go over the finished picture, under the text so their labels stay readable
+        graph.add_pass(GraphPass::new("debug", PassKind::Debug, &[Attachment::Frame], &[Attachment::Frame]));
+        graph.add_pass(GraphPass::new("text", PassKind::Text, &[Attachment::Frame], &[Attachment::Frame]));
+        graph
+    }
+}
+
+impl RenderGraph {
+    pub fn add_pass(&mut self, pass: GraphPass) {
+        self.passes.push(pass);
+        self.changed = true;
+    }
+
+    // Declared right before the named pass, or at the end if there's no such pass
+    pub fn insert_pass_before(&mut self, before: &str, pass: GraphPass) {
+        let index = self.position(before).unwrap_or(self.passes.len());
+        self.passes.insert(index, pass);
+        self.changed = true;
+    }
+
+    pub fn remove_pass(&mut self, name: &str) -> Option<GraphPass> {
+        let index = self.position(name)?;
+        self.changed = true;
+        Some(self.passes.remove(index))
+    }
+
+    // Redeclares a pass right before another one
+    pub fn move_pass_before(&mut self, name: &str, before: &str) {
+        if let Some(pass) = self.remove_pass(name) {
+            self.insert_pass_before(before, pass);
+        }
+    }
+
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        if let Some(index) = self.position(name) {
+            self.passes[index].enabled = enabled;
+            self.changed = true;
+        }
+    }
+
+    pub fn add_dependency(&mut self, name: &str, after: &str) {
+        if let Some(index) = self.position(name) {
+            self.passes[index].after.push(after.to_owned());
+            self.changed = true;
+        }
+    }
+
+    // Compiled order of the enabled passes, cached until the graph changes. A graph that doesn't compile keeps
+    // getting drawn in the last order that did
+    pub fn order(&mut self) -> &[GraphPass] {
+        if self.changed {
+            self.changed = false;
+            match self.compile() {
+                Ok((order, warnings)) => {
+                    for warning in warnings.iter().filter(|warning| !self.warnings.contains(warning)) {
+                        println!("Render graph: {}", warning);
+                    }
+                    self.order = order;
+                    self.warnings = warnings;
+                },
+                Err(error) => println!("{}, keeping the last order that worked", error),
+            }
+        }
+        &self.order
+    }
+
+    // The order, and whatever looks off about the graph without stopping it from running
+    fn compile(&self) -> Result<(Vec<GraphPass>, Vec<String>)> {
+        let enabled: Vec<&GraphPass> = self.passes.iter().filter(|pass| pass.enabled).collect();
+        // Without lighting the sprites draw straight into the scene
+        let unlit = !enabled.iter().any(|pass| pass.kind == PassKind::Lighting);
+        let texture = |attachment: &Attachment| if unlit && *attachment == Attachment::Albedo { Attachment::Scene } else { *attachment };
+
+        // dependencies[i] holds the passes that have to run before pass i
+        let mut dependencies: Vec<Vec<usize>> = vec![Vec::new(); enabled.len()];
+        let mut warnings = Vec::new();
+        for (i, pass) in enabled.iter().enumerate() {
+            match pass.kind.fixed_writes() {
+                Some(fixed_writes) if pass.writes != fixed_writes => {
+                    warnings.push(format!("{} always draws into {:?}, not {:?}", pass.name, fixed_writes, pass.writes));
+                },
+                None if pass.writes.contains(&Attachment::Frame) && pass.writes.len() > 1 => {
+                    warnings.push(format!("{} can draw into the frame or the scene but not both, it goes with the frame", pass.name));
+                },
+                _ => {},
+            }
+            for attachment in pass.reads.iter() {
+                let writers: Vec<usize> = (0..i).filter(|j| enabled[*j].writes.iter().any(|written| texture(written) == texture(attachment))).collect();
+                if writers.is_empty() {
+                    warnings.push(format!("{} reads {:?} but no pass before it writes it", pass.name, attachment));
+                }
+                dependencies[i].extend(writers);
+            }
+            for after in pass.after.iter() {
+                // Disabled or missing passes don't hold anything up
+                if let Some(j) = enabled.iter().position(|other| other.name == *after) {
+                    dependencies[i].push(j);
+                }
+            }
+        }
+
+        // Whatever is ready and was declared first goes next
+        let mut order: Vec<usize> = Vec::with_capacity(enabled.len());
+        while order.len() < enabled.len() {
+            let next = (0..enabled.len()).find(|i| !order.contains(i) && dependencies[*i].iter().all(|j| order.contains(j)));
+            match next {
+                Some(next) => order.push(next),
+                None => {
+                    let stuck: Vec<&str> = (0..enabled.len()).filter(|i| !order.contains(i)).map(|i| enabled[i].name.as_str()).collect();
+                    return Err(anyhow!("Render graph has a dependency cycle between {}", stuck.join(", ")));
+                },
+            }
+        }
+
+        // Whatever a pass writes has to get read by something later, the frame is what gets shown. Passes that write
+        // without reading replace what was there
+        let reads = |i: usize, attachment: Attachment| enabled[i].reads.iter().any(|read| texture(read) == attachment);
+        let writes = |i: usize, attachment: Attachment| enabled[i].writes.iter().any(|written| texture(written) == attachment);
+        for (k, &i) in order.iter().enumerate() {
+            for attachment in enabled[i].writes.iter().map(texture) {
+                let next = order[k + 1..].iter().copied().find(|&j| reads(j, attachment) || writes(j, attachment));
+                match next {
+                    Some(j) if !reads(j, attachment) => {
+                        warnings.push(format!("{} draws over what {} put into {:?}", enabled[j].name, enabled[i].name, attachment));
+                    },
+                    None if attachment != Attachment::Frame => {
+                        if let Some(&j) = order[..k].iter().find(|&&j| reads(j, attachment)) {
+                            warnings.push(format!("{} writes {:?} after {} already read it", enabled[i].name, attachment, enabled[j].name));
+                        }
+                    },
+                    _ => {},
+                }
+            }
+        }
+        Ok((order.into_iter().map(|i| enabled[i].clone()).collect(), warnings))
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.passes.iter().position(|pass| pass.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(passes: &[GraphPass]) -> Vec<PassKind> {
+        passes.iter().map(|pass| pass.kind).collect()
+    }
+
+    #[test]
+    fn default_graph_runs_in_declaration_order() {
+        let mut graph = RenderGraph::default();
+        assert_eq!(kinds(graph.order()), &[PassKind::World, PassKind::Lighting, PassKind::PostProcess, PassKind::Ui, PassKind::Debug, PassKind::Text]);
+        assert!(graph.compile().unwrap().1.is_empty());
+    }
+
+    #[test]
+    fn disabled_passes_are_left_out() {
+        let mut graph = RenderGraph::default();
+        graph.set_enabled("ui", false);
+        assert_eq!(kinds(graph.order()), &[PassKind::World, PassKind::Lighting, PassKind::PostProcess, PassKind::Debug, PassKind::Text]);
+    }
+
+    #[test]
+    fn dependencies_move_passes_later() {
+        let mut graph = RenderGraph::default();
+        graph.insert_pass_before("post_process", GraphPass::new("late_debug", PassKind::Debug, &[], &[]));
+        graph.add_dependency("late_debug", "text");
+        assert_eq!(graph.order().last().map(|pass| pass.name.as_str()), Some("late_debug"));
+        assert_eq!(graph.order().len(), 7);
+    }
+
+    #[test]
+    fn a_cycle_keeps_the_last_order_that_compiled() {
+        let mut graph = RenderGraph::default();
+        let before = graph.order().to_vec();
+        graph.add_dependency("world", "text");
+        assert!(graph.compile().is_err());
+        assert_eq!(graph.order(), before.as_slice());
+    }
+
+    #[test]
+    fn a_missing_writer_is_a_warning_not_an_error() {
+        let mut graph = RenderGraph::default();
+        graph.set_enabled("world", false);
+        let (order, warnings) = graph.compile().unwrap();
+        assert_eq!(order.len(), 5);
+        assert_eq!(warnings, vec![
+            "lighting reads Albedo but no pass before it writes it".to_owned(),
+            "lighting reads Normals but no pass before it writes it".to_owned(),
+        ]);
+    }
+
+    #[test]
+    fn without_lighting_albedo_is_the_scene() {
+        let mut graph = RenderGraph::default();
+        graph.set_enabled("lighting", false);
+        let (order, warnings) = graph.compile().unwrap();
+        assert_eq!(kinds(&order), &[PassKind::World, PassKind::PostProcess, PassKind::Ui, PassKind::Debug, PassKind::Text]);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn ui_moved_before_the_post_effects_gets_drawn_over() {
+        let mut graph = RenderGraph::default();
+        graph.move_pass_before("ui", "world");
+        let (order, warnings) = graph.compile().unwrap();
+        assert_eq!(order[0].kind, PassKind::Ui);
+        assert_eq!(warnings, vec![
+            "ui reads Frame but no pass before it writes it".to_owned(),
+            "post_process draws over what ui put into Frame".to_owned(),
+        ]);
+    }
+
+    #[test]
+    fn ui_drawn_into_the_scene_after_it_was_lit_never_shows_up() {
+        let mut graph = RenderGraph::default();
+        graph.remove_pass("ui");
+        graph.add_pass(GraphPass::new("ui", PassKind::Ui, &[Attachment::Albedo, Attachment::Normals], &[Attachment::Albedo, Attachment::Normals]));
+        let (order, warnings) = graph.compile().unwrap();
+        assert_eq!(order.last().map(|pass| pass.kind), Some(PassKind::Ui));
+        assert_eq!(warnings, vec![
+            "ui writes Albedo after lighting already read it".to_owned(),
+            "ui writes Normals after lighting already read it".to_owned(),
+        ]);
+    }
+
+    #[test]
+    fn passes_that_always_draw_into_the_frame_say_so() {
+        let mut graph = RenderGraph::default();
+        graph.add_pass(GraphPass::new("more_text", PassKind::Text, &[Attachment::Scene], &[Attachment::Scene]));
+        let (_, warnings) = graph.compile().unwrap();
+        assert_eq!(warnings[0], "more_text always draws into [Frame], not [Scene]");
+    }
+}
@@ -1,9 +1,48 @@
-use crate::texture::Texture;
-use std::{mem};
+use crate::texture::{Texture, SpriteInstance};
+pub use crate::texture::TextureOptions;
+use crate::capture::FrameCapture;
+use crate::shader_watcher::{ShaderWatcher, ValidationErrorScope};
+use crate::material::Material;
+use crate::post_process::{PostProcessor, RenderTarget};
+pub use crate::post_process::PostEffect;
+use crate::lighting::{Lighting, NORMAL_FORMAT};
+use crate::bitmap_font::BitmapFont;
+pub use crate::debug_draw::DebugDraw;
+use crate::frame_pacer::FramePacer;
+pub use crate::frame_pacer::FrameStats;
+pub use crate::stats::RenderStats;
+use crate::render_graph::{RenderGraph, GraphPass, PassKind, Attachment};
+pub use crate::lighting::{RenderableLight, SceneLighting};
+pub use crate::capture::RecordingMode;
+use std::{fmt, mem};
+use std::ops::Range;
+use std::time::{Duration, Instant};
 use futures::executor::{LocalPool, LocalSpawner};
 use futures::task::SpawnExt;
 use wgpu::{DepthBiasState, MultisampleState, PrimitiveState, util::{DeviceExt, StagingBelt}};
-use wgpu_glyph::{GlyphBrush, GlyphBrushBuilder, Section, Text, ab_glyph};
+use wgpu_glyph::{GlyphBrush, GlyphBrushBuilder, Layout, Section, Text, ab_glyph};
+use wgpu_glyph::ab_glyph::{Font, ScaleFont};
+
+// Anything lower is unreadable
+const MIN_RENDER_SCALE: f32 = 0.1;
+// Two triangles, in the same order as the corners used to be written out per sprite
+const UNIT_QUAD: [[f32; 2]; 6] = [[0., 0.], [0., 1.], [1., 1.], [0., 0.], [1., 1.], [1., 0.]];
+// Room for this many sprites up front, the instance buffer doubles whenever a frame needs more
+const INITIAL_INSTANCE_CAPACITY: usize = 1024;
+
+// Regular "over" compositing, keeping the destination alpha sensible instead of min-ing it
+const ALPHA_BLENDING: wgpu::BlendState = wgpu::BlendState {
+    color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::SrcAlpha,
+        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+        operation: wgpu::BlendOperation::Add,
+    },
+    alpha: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+        operation: wgpu::BlendOperation::Add,
+    },
+};
 
 pub struct Renderer {
     surface: wgpu::Surface,
@@ -12,38 +51,71 @@ pub struct Renderer {
     sc_desc: wgpu::SwapChainDescriptor,
     swap_chain: wgpu::SwapChain,
     size: winit::dpi::PhysicalSize<u32>,
-    render_pipeline: wgpu::RenderPipeline,
-    depth_texture: Texture,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    scene_pipelines: SpritePipelines,
+    frame_pipelines: SpritePipelines,
+    depth_texture: Texture, // Scene sized, multisampled along with the scene
+    frame_depth_texture: Texture, // Frame sized, for the sprites drawn after the post effects
+    msaa_samples: u32,
+    msaa_targets: Option<MsaaTargets>,
+    render_scale: f32,
     texture_bind_group_layout: wgpu::BindGroupLayout,
+    quad_buffer: wgpu::Buffer, // UNIT_QUAD, every sprite is an instance of it
+    instance_buffer: wgpu::Buffer,
+    instance_capacity: usize,
     textures: Vec<Texture>,
     desired_res: winit::dpi::PhysicalSize<u32>,
     glyph_brush: GlyphBrush<()>,
+    fonts: Vec<LoadedFont>, // Indexed by FontId
     staging_belt: StagingBelt,
     staging_belt_local_pool: LocalPool,
     staging_belt_local_spawner: LocalSpawner,
+    frame_capture: FrameCapture,
+    clear_color: wgpu::Color,
+    shader_watcher: Option<ShaderWatcher>,
+    sprite_vs_module: wgpu::ShaderModule,
+    material_bind_group_layout: wgpu::BindGroupLayout,
+    material_pipeline_layout: wgpu::PipelineLayout,
+    materials: Vec<Material>,
+    start_time: Instant,
+    post_processor: PostProcessor,
+    lighting: Lighting,
+    flat_normal_map: usize,
+    unlit_normal_map: usize,
+    white_texture: usize,
+    missing_texture: usize,
+    debug_draw: DebugDraw,
+    frame_pacer: FramePacer,
+    render_stats: RenderStats, // Counted up while drawing, complete once render returns
+    render_graph: RenderGraph,
 }
 
 impl Renderer {
-    pub async fn new(window: &winit::window::Window, desired_res: winit::dpi::PhysicalSize<u32>) -> Self {
+    pub async fn new(window: &winit::window::Window, desired_res: winit::dpi::PhysicalSize<u32>, config: &RendererConfig) -> Result<Self, RendererError> {
         let size = window.inner_size();
         
-        let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+        let instance = wgpu::Instance::new(config.backends);
         let surface = unsafe { instance.create_surface(window) };
         let adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::HighPerformance,
+            power_preference: config.power_preference,
             compatible_surface: Some(&surface),
-        }).await.unwrap();
+        }).await.ok_or(RendererError::NoAdapter)?;
         
         let (device, queue) = adapter.request_device(&wgpu::DeviceDescriptor {
             features: wgpu::Features::empty(),
             limits: wgpu::Limits::default(),
             label: None
-        }, None).await.unwrap();
+        }, None).await.map_err(RendererError::RequestDevice)?;
+
+        // Whatever the surface likes best unless asked for something specific
+        let render_format = config.format
+            .or_else(|| adapter.get_swap_chain_preferred_format(&surface))
+            .unwrap_or(wgpu::TextureFormat::Bgra8UnormSrgb);
 
         // Setup font rendering
-        let render_format = wgpu::TextureFormat::Bgra8UnormSrgb;
-        let font = ab_glyph::FontArc::try_from_slice(include_bytes!("../res/font/PressStart2P-Regular.ttf")).unwrap();
-        let glyph_brush = GlyphBrushBuilder::using_font(font).build(&device, render_format);
+        let font = ab_glyph::FontArc::try_from_slice(include_bytes!("../res/font/PressStart2P-Regular.ttf"))
+            .map_err(|error| RendererError::Font { path: "res/font/PressStart2P-Regular.ttf".to_owned(), error: anyhow::anyhow!("{}", error) })?;
+        let glyph_brush = GlyphBrushBuilder::using_font(font.clone()).build(&device, render_format);
 
         // The font library requires a staging belt that has to be synced manually unfortunately
         // TODO: Check if you could load the font to gpu and just draw instanced on chars?
@@ -56,7 +128,7 @@ impl Renderer {
             format: render_format,
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
+            present_mode: config.vsync.present_mode(),
         };
         let swap_chain = device.create_swap_chain(&surface, &sc_desc);
         
@@ -69,7 +141,7 @@ impl Renderer {
                         ty: wgpu::BindingType::Texture {
                             multisampled: false, 
                             view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float {filterable: false,},
+                            sample_type: wgpu::TextureSampleType::Float {filterable: true,},
                         },
                         count: None,   
                     },
@@ -87,280 +159,1524 @@ impl Renderer {
             }
         );
 
-        let depth_texture = Texture::create_depth_texture(&device, &sc_desc, "depth_texture");
-
-        let render_pipeline = {
-            let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[
-                    &texture_bind_group_layout,
-                ],
-                push_constant_ranges: &[],
-            });
+        // Only 1 and 4 samples work on every backend
+        let msaa_samples = match config.msaa_samples {
+            0 | 1 => 1,
+            4 => 4,
+            samples => {
+                println!("{}x MSAA isn't supported everywhere, using 4x", samples);
+                4
+            },
+        };
+        let render_scale = config.render_scale.clamp(MIN_RENDER_SCALE, 1.);
+        let [scene_width, scene_height] = scaled_size(&sc_desc, render_scale);
+        let depth_texture = Texture::create_depth_texture(&device, scene_width, scene_height, msaa_samples, "depth_texture");
+        let frame_depth_texture = Texture::create_depth_texture(&device, sc_desc.width.max(1), sc_desc.height.max(1), 1, "frame_depth_texture");
 
-            let vs_module = device.create_shader_module(&wgpu::include_spirv!("shader.vert.spv"));
+        let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                &texture_bind_group_layout,
+                // Normal map
+                &texture_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        // Materials reuse the sprite vertex shader, so keep it around for the ones registered later
+        let sprite_vs_module = device.create_shader_module(&wgpu::include_spirv!("shader.vert.spv"));
+        let (scene_pipelines, frame_pipelines) = {
             let fs_module = device.create_shader_module(&wgpu::include_spirv!("shader.frag.spv"));
-            
-            // TODO: maybe extracting it would help creating multiple pipelines in case lights are added or something?
-            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor{
-                label: None,
-                layout: Some(&render_pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &vs_module,
-                    entry_point: "main",
-                    buffers: &[
-                        wgpu::VertexBufferLayout {
-                            array_stride: std::mem::size_of::<crate::texture::Vertex>() as wgpu::BufferAddress,
-                            step_mode: wgpu::InputStepMode::Vertex,
-                            attributes: &[
-                                wgpu::VertexAttribute {
-                                    offset: 0,
-                                    shader_location: 0,
-                                    format: wgpu::VertexFormat::Float32x3,
-                                },
-                                wgpu::VertexAttribute {
-                                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                                    shader_location: 1,
-                                    format: wgpu::VertexFormat::Float32x2,
-                                }
-                            ],
-                        }
-                    ],
-                },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: Texture::DEPTH_FORMAT,
-                    depth_write_enabled: true,
-                    bias: DepthBiasState::default(),
-                    depth_compare: wgpu::CompareFunction::Less,
-                    stencil: wgpu::StencilState::default(),
-                }),
-                fragment: Some(wgpu::FragmentState {
-                    module: &fs_module,
-                    entry_point: "main",
-                    targets: &[
-                        wgpu::ColorTargetState {
-                            format: sc_desc.format,
-                            write_mask: wgpu::ColorWrite::ALL,
-                            blend: Some(wgpu::BlendState {
-                                alpha: wgpu::BlendComponent {
-                                    src_factor: wgpu::BlendFactor::One,
-                                    dst_factor: wgpu::BlendFactor::One,
-                                    operation: wgpu::BlendOperation::Min,
-                                },
-                                color: wgpu::BlendComponent {
-                                    src_factor: wgpu::BlendFactor::SrcAlpha,
-                                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                                    operation: wgpu::BlendOperation::Add,
-                                },
-                            })
-                        }
-                    ]
-                }),
-                multisample: MultisampleState {
-                    alpha_to_coverage_enabled: false,
-                    count: 1,
-                    mask: !0,
-                },
-                primitive: PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    clamp_depth: false,
-                    conservative: false,
-                    cull_mode: Some(wgpu::Face::Back),
-                    front_face: wgpu::FrontFace::Ccw,
-                    strip_index_format: None,
-                    polygon_mode: wgpu::PolygonMode::Fill,
-                },
-            })
+            let scene_pipelines = SpritePipelines::new(&device, &render_pipeline_layout, &sprite_vs_module, &fs_module, sc_desc.format, SpriteTarget::Scene, msaa_samples);
+            let frame_pipelines = SpritePipelines::new(&device, &render_pipeline_layout, &sprite_vs_module, &fs_module, sc_desc.format, SpriteTarget::Frame, 1);
+            (scene_pipelines, frame_pipelines)
         };
 
-        Self { 
+        let material_bind_group_layout = Material::create_bind_group_layout(&device);
+        let material_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                &texture_bind_group_layout,
+                &material_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let quad_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("unit_quad"),
+            contents: bytemuck::cast_slice(&UNIT_QUAD),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+        let instance_buffer = create_instance_buffer(&device, INITIAL_INSTANCE_CAPACITY);
+
+        let post_processor = PostProcessor::new(&device, &queue, sc_desc.format)
+            .map_err(|error| RendererError::Texture { path: "res/lut/warm.png".to_owned(), error })?;
+        let lighting = Lighting::new(&device, sc_desc.format);
+        let debug_draw = DebugDraw::new(&device, sc_desc.format, desired_res.width as f32 / desired_res.height as f32);
+
+        let mut renderer = Self { 
             size, 
             surface, 
             device, queue, 
             sc_desc, 
             swap_chain, 
             depth_texture, 
-            render_pipeline, 
+            frame_depth_texture,
+            msaa_samples,
+            msaa_targets: None,
+            render_scale,
+            render_pipeline_layout, 
+            scene_pipelines,
+            frame_pipelines,
             texture_bind_group_layout, 
+            quad_buffer,
+            instance_buffer,
+            instance_capacity: INITIAL_INSTANCE_CAPACITY,
             textures: vec![],
             desired_res,
             glyph_brush,
+            fonts: vec![LoadedFont::Vector { font, glyph_font_id: wgpu_glyph::FontId(0) }],
             staging_belt,
             staging_belt_local_pool,
             staging_belt_local_spawner,
-        }
+            frame_capture: FrameCapture::new(),
+            clear_color: wgpu::Color::BLACK,
+            shader_watcher: None,
+            sprite_vs_module,
+            material_bind_group_layout,
+            material_pipeline_layout,
+            materials: vec![],
+            start_time: Instant::now(),
+            post_processor,
+            lighting,
+            flat_normal_map: 0,
+            unlit_normal_map: 0,
+            white_texture: 0,
+            missing_texture: 0,
+            debug_draw,
+            frame_pacer: FramePacer::new(config.target_fps),
+            render_stats: RenderStats::default(),
+            render_graph: RenderGraph::default(),
+        };
+
+        // Normal maps for sprites that don't have one - alpha tells the lighting pass whether to light them at all
+        renderer.flat_normal_map = renderer.register_image(&image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([128, 128, 255, 255]))), "flat_normal_map", &TextureOptions { srgb: false, ..Default::default() })?;
+        renderer.white_texture = renderer.register_image(&image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255]))), "white_texture", &TextureOptions::default())?;
+        renderer.unlit_normal_map = renderer.register_image(&image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([128, 128, 255, 0]))), "unlit_normal_map", &TextureOptions { srgb: false, ..Default::default() })?;
+        // Stands in for textures that didn't load, loud enough to get noticed
+        renderer.missing_texture = renderer.register_image(&image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 0, 255, 255]))), "missing_texture", &TextureOptions::default())?;
+
+        // Built in materials. Per sprite params: flash - how white (0 to 1), dissolve - how far gone (0 to 1), outline - strength (0 to 1)
+        renderer.register_material("flash", &wgpu::include_spirv!("materials/flash.frag.spv"), [1., 1., 1., 0.]);
+        renderer.register_material("dissolve", &wgpu::include_spirv!("materials/dissolve.frag.spv"), [1., 0.55, 0.1, 0.08]);
+        renderer.register_material("outline", &wgpu::include_spirv!("materials/outline.frag.spv"), [1., 1., 1., 1.]);
+
+        Ok(renderer)
+    }
+    // Call once at the start of every frame. Holds the frame back when there's an FPS cap and returns
+    // the time since the previous frame started
+    pub fn begin_frame(&mut self) -> Duration {
+        self.frame_pacer.begin_frame()
+    }
+    pub fn frame_stats(&self) -> &FrameStats {
+        self.frame_pacer.stats()
+    }
+    // Draw calls and friends of the last render
+    pub fn render_stats(&self) -> &RenderStats {
+        &self.render_stats
     }
-    pub fn render(&mut self, renderables: &Vec<Renderable>, renderable_texts: &Vec<RenderableText>) {
+    pub fn render(&mut self, renderables: &Vec<Renderable>, renderable_particles: &Vec<RenderableParticles>, renderable_texts: &Vec<RenderableText>, lighting: &Option<SceneLighting>) -> Result<(), RendererError> {
+        let render_start = Instant::now();
+        self.render_stats = RenderStats::default();
+        self.reload_shaders_if_changed();
+
+        let time = self.start_time.elapsed().as_secs_f32();
+        for material in self.materials.iter() {
+            material.write_uniforms(&self.queue, time);
+        }
+
         // SEND BUFFERS AND SHIT TO GPU AND RENDER
-        let frame = self.swap_chain.get_current_frame()
-            .expect("Didnt get frame")
-            .output;
+        // Frames that can't be had get skipped, the queued debug shapes go with them
+        if self.is_minimized() {
+            self.debug_draw.clear();
+            return Ok(());
+        }
+        let frame = match self.swap_chain.get_current_frame() {
+            Ok(frame) => frame.output,
+            // Minimizing or resizing leaves the swap chain stale, a new one is good from the next frame on
+            Err(wgpu::SwapChainError::Lost) | Err(wgpu::SwapChainError::Outdated) => {
+                self.recreate_swap_chain();
+                self.debug_draw.clear();
+                return Ok(());
+            },
+            Err(wgpu::SwapChainError::Timeout) => {
+                self.debug_draw.clear();
+                return Ok(());
+            },
+            Err(wgpu::SwapChainError::OutOfMemory) => return Err(RendererError::OutOfMemory),
+        };
 
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: None,
         });
 
-        {
-            let mut buffers: Vec<wgpu::Buffer> = vec![];
-            for renderable in renderables {
-                if renderable.texture_id as usize > (self.textures.len() - 1) {
-                    println!("Wrong texture id {:?}. Can't create buffer", renderable.texture_id);
+        let debug_texts = self.debug_draw.take_texts();
+        let renderable_texts: Vec<&RenderableText> = renderable_texts.iter().chain(debug_texts.iter()).collect();
+        // Swap chain frames can't be read back, so a frame that's wanted goes into the capture target instead,
+        // gets copied onto the swap chain frame from there and into a buffer to read back
+        let mut capture_buffer: Option<wgpu::Buffer> = None;
+        if self.frame_capture.wants_frame() {
+            let capture_target = self.frame_capture.take_target(&self.device, &self.sc_desc);
+            self.draw_frame(&mut encoder, &capture_target.view, renderables, renderable_particles, &renderable_texts, lighting)?;
+            self.post_processor.blit(&self.device, &self.queue, &mut encoder, &capture_target.view, capture_target.id, &frame.view, [self.sc_desc.width, self.sc_desc.height], &mut self.render_stats);
+            self.frame_capture.put_target(capture_target);
+            capture_buffer = self.frame_capture.copy_to_buffer(&self.device, &mut encoder);
+        } else {
+            self.draw_frame(&mut encoder, &frame.view, renderables, renderable_particles, &renderable_texts, lighting)?;
+        }
+
+        self.staging_belt.finish();
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        self.staging_belt_local_spawner
+            .spawn(self.staging_belt.recall())
+            .map_err(RendererError::StagingBelt)?;
+
+        self.staging_belt_local_pool.run_until_stalled();
+        self.debug_draw.clear();
+        self.frame_pacer.record_render_time(render_start.elapsed());
+
+        if let Some(capture_buffer) = capture_buffer {
+            self.frame_capture.read_back(&self.device, capture_buffer, self.sc_desc.format);
+        }
+        if let Err(error) = self.frame_capture.save_ready_frames(&self.device) {
+            println!("Couldn't save captured frame: {:?}", error);
+        }
+        Ok(())
+    }
+
+    fn draw_frame(&mut self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, renderables: &Vec<Renderable>, renderable_particles: &Vec<RenderableParticles>, renderable_texts: &[&RenderableText], lighting: &Option<SceneLighting>) -> Result<(), RendererError> {
+        // The world goes into an offscreen texture so the post effects can work on it before it reaches the frame,
+        // and into yet another one first when it has to be lit
+        let [scene_width, scene_height] = target_size(&self.sc_desc, self.render_scale, SpriteTarget::Scene);
+        self.post_processor.prepare(&self.device, scene_width, scene_height);
+        self.lighting.prepare(&self.device, scene_width, scene_height);
+        self.prepare_scene_targets(scene_width, scene_height);
+        self.prepare_frame_depth();
+
+        let passes = self.render_graph.order().to_vec();
+        // World colors only take the detour through the albedo target when something is going to light them
+        let lit = lighting.is_some() && passes.iter().any(|pass| pass.kind == PassKind::Lighting);
+
+        // Bitmap font text is made of sprites, so it goes into the UI pass with the rest of the UI, snapped to the pixels
+        // of the frame it draws into
+        let placed_texts: Vec<Vec<PlacedSegment>> = renderable_texts.iter().map(|renderable_text| self.place_text(renderable_text)).collect();
+        let ui_size = target_size(&self.sc_desc, self.render_scale, SpriteTarget::Frame);
+        let bitmap_texts: Vec<Vec<Renderable>> = renderable_texts.iter().zip(placed_texts.iter())
+            .map(|(renderable_text, placed)| self.bitmap_text_quads(renderable_text, placed, ui_size))
+            .collect();
+        let sprite_batches = self.prepare_sprites(encoder, renderables, renderable_particles, &bitmap_texts);
+
+        // Only the first sprite pass into the scene clears it
+        let mut cleared = false;
+        for pass in passes.iter() {
+            match pass.kind {
+                // The UI goes over the post effects at full resolution by default, so curvature, pixelation and the
+                // render scale leave it lined up with the text on it
+                PassKind::World | PassKind::Ui => {
+                    let batches = if pass.kind == PassKind::World { &sprite_batches.world } else { &sprite_batches.ui };
+                    let target = sprite_target(pass);
+                    let clear = target == SpriteTarget::Scene && !cleared;
+                    self.sprite_pass(encoder, batches, target, view, lit, clear);
+                    cleared |= clear;
+                },
+                PassKind::Lighting => {
+                    if let Some(lighting) = lighting {
+                        self.lighting.apply(&self.device, encoder, lighting, self.post_processor.scene_view(), &mut self.render_stats);
+                    }
+                },
+                // Post effects, vector text stays crisp on top of them
+                PassKind::PostProcess => {
+                    self.post_processor.apply(&self.queue, encoder, view, [self.sc_desc.width, self.sc_desc.height], self.start_time.elapsed().as_secs_f32(), &mut self.render_stats);
+                },
+                PassKind::Debug => self.debug_draw.draw(&self.device, encoder, view, &mut self.render_stats),
+                PassKind::Text => self.text_pass(encoder, view, renderable_texts, &placed_texts)?,
+            }
+        }
+        Ok(())
+    }
+    // Sorts everything made of sprites, uploads an instance per quad and groups them into draw calls for the world and UI passes
+    fn prepare_sprites(&mut self, encoder: &mut wgpu::CommandEncoder, renderables: &Vec<Renderable>, renderable_particles: &Vec<RenderableParticles>, bitmap_texts: &[Vec<Renderable>]) -> SpriteBatches {
+        // Back to front: by layer, then z, then the bottom edge so whatever stands lower on screen overlaps what's above it
+        let mut sorted: Vec<(SortedItem, f32)> = Vec::with_capacity(renderables.len() + renderable_particles.len());
+        for renderable in renderables {
+            if let Some(texture) = self.textures.get(renderable.texture_id) {
+                let bottom = if renderable.use_texture_size {
+                    renderable.p1[1] + texture.height as f32 / self.desired_res.height as f32
                 } else {
-                    // TODO: HOW TO RENDER RESOLUTIONS OTHER THAN 16:9??
-                    // assume target resolution of 1280x720 keeping the horizontal ratio steady
-                    let horiz_ratio = 1.; //self.size.width as f32 / self.desired_res.width as f32;
-                    let vert_ration = 1.; //self.size.height as f32 / self.desired_res.height as f32;
-                    
-                    // calculate points based on the ratio and stuff
-                    let p1x = renderable.p1[0] * vert_ration * 2.0 - 1.0;
-                    let p1y = -(renderable.p1[1] * horiz_ratio * 2.0 - 1.0);
-                    let (p2x, p2y) = {
-                        if renderable.use_texture_size {
-                            let texture = self.textures.get(renderable.texture_id as usize).unwrap();
-                            let x = (renderable.p1[0] + texture.width as f32 / self.desired_res.width as f32) * vert_ration * 2.0 - 1.;
-                            let y = -((renderable.p1[1] + texture.height as f32 / self.desired_res.height as f32) * horiz_ratio * 2.0 - 1.);
-                            (x, y)
-                        } else {
-                            (renderable.p2[0] * vert_ration * 2.0 - 1.0, -(renderable.p2[1] * horiz_ratio * 2.0 - 1.0))
-                        }
+                    renderable.p2[1]
+                };
+                sorted.push((SortedItem::Sprite(renderable), bottom));
+            } else {
+                println!("Wrong texture id {:?}. Can't render", renderable.texture_id);
+            }
+        }
+        for particles in renderable_particles {
+            if self.textures.get(particles.texture_id).is_some() {
+                sorted.push((SortedItem::Particles(particles), particles.sort_y));
+            } else {
+                println!("Wrong texture id {:?}. Can't render particles", particles.texture_id);
+            }
+        }
+        for quads in bitmap_texts.iter().filter(|quads| !quads.is_empty()) {
+            sorted.push((SortedItem::BitmapText(quads), f32::MAX));
+        }
+        sorted.sort_by(|(a, a_bottom), (b, b_bottom)| {
+            a.layer().cmp(&b.layer())
+                .then(a.z().cmp(&b.z()))
+                .then(a_bottom.partial_cmp(b_bottom).unwrap_or(std::cmp::Ordering::Equal))
+        });
+
+        // Every item gets its own depth from the sorted order so opaque sprites can hide whatever is behind them
+        let mut instances: Vec<SpriteInstance> = Vec::with_capacity(sorted.len());
+        let mut sprite_batches = SpriteBatches::default();
+        for (i, (item, _)) in sorted.iter().enumerate() {
+            let depth = 1.0 - (i + 1) as f32 / (sorted.len() + 1) as f32;
+            let first_instance = instances.len() as u32;
+            let pass_batches = if item.layer() == RenderLayer::Ui { &mut sprite_batches.ui } else { &mut sprite_batches.world };
+            match item {
+                SortedItem::Sprite(renderable) => {
+                    match &renderable.nine_slice {
+                        Some(nine_slice) => instances.extend(self.nine_slice_instances(renderable, nine_slice, depth)),
+                        None => instances.push(self.renderable_instance(renderable, depth)),
+                    }
+
+                    // Materials can make anything see-through, so they always get blended
+                    let material_id = renderable.material_id.filter(|material_id| *material_id < self.materials.len());
+                    let texture = &self.textures[renderable.texture_id];
+                    let opaque = material_id.is_none() && texture.opaque && renderable.tint[3] * renderable.opacity >= 1.0;
+                    let normal_map_id = if !renderable.lit {
+                        self.unlit_normal_map
+                    } else {
+                        renderable.normal_map_id.filter(|normal_map_id| *normal_map_id < self.textures.len()).unwrap_or(self.flat_normal_map)
                     };
-                    
-                    let (tex_x1, tex_y1, tex_x2, tex_y2) = {
-                        if !renderable.horiz_mirror {
-                            (0., 0., 1., 1.)
-                        } else {
-                            (1., 0., 0., 1.)
-                        }
+                    let batches = if opaque { &mut pass_batches.opaque } else { &mut pass_batches.transparent };
+                    push_to_batches(batches, material_id, renderable.texture_id, normal_map_id, first_instance..instances.len() as u32);
+                },
+                SortedItem::Particles(particles) => {
+                    // The whole emitter goes out in one draw call
+                    for particle in particles.particles.iter() {
+                        let quad = Renderable {
+                            texture_id: particles.texture_id,
+                            p1: [particle.center[0] - particle.size[0] / 2., particle.center[1] - particle.size[1] / 2.],
+                            p2: [particle.center[0] + particle.size[0] / 2., particle.center[1] + particle.size[1] / 2.],
+                            tint: particle.color,
+                            ..Default::default()
+                        };
+                        instances.push(self.renderable_instance(&quad, depth));
+                    }
+                    if !particles.particles.is_empty() {
+                        push_to_batches(&mut pass_batches.transparent, None, particles.texture_id, self.flat_normal_map, first_instance..instances.len() as u32);
+                    }
+                },
+                SortedItem::BitmapText(quads) => {
+                    // Glyphs can be spread over several pages, consecutive ones on the same page still share a draw call
+                    for quad in quads.iter() {
+                        let quad_instance = instances.len() as u32;
+                        instances.push(self.renderable_instance(quad, depth));
+                        push_to_batches(&mut pass_batches.transparent, None, quad.texture_id, self.unlit_normal_map, quad_instance..instances.len() as u32);
+                    }
+                },
+            }
+        }
+        // The instances go up through the staging belt into a buffer that's kept around between frames
+        if instances.len() > self.instance_capacity {
+            self.instance_capacity = instances.len().next_power_of_two();
+            self.instance_buffer = create_instance_buffer(&self.device, self.instance_capacity);
+            self.render_stats.buffer_allocations += 1;
+        }
+        let instance_bytes: &[u8] = bytemuck::cast_slice(&instances);
+        if let Some(size) = wgpu::BufferSize::new(instance_bytes.len() as u64) {
+            self.staging_belt.write_buffer(encoder, &self.instance_buffer, 0, size, &self.device).copy_from_slice(instance_bytes);
+        }
+        self.render_stats.instances += instances.len() as u32;
+        self.render_stats.vertices += (instances.len() * UNIT_QUAD.len()) as u32;
+        sprite_batches
+    }
+    // Draws one pass worth of sprites. In the scene they go into the albedo target when it gets lit and straight into
+    // the scene otherwise, normals alongside. In the frame they go over whatever is there, depth tested only against each other
+    fn sprite_pass(&mut self, encoder: &mut wgpu::CommandEncoder, batches: &PassBatches, target: SpriteTarget, frame_view: &wgpu::TextureView, lit: bool, clear: bool) {
+        if !clear && batches.opaque.is_empty() && batches.transparent.is_empty() {
+            return;
+        }
+        let load = |color: wgpu::Color| if clear { wgpu::LoadOp::Clear(color) } else { wgpu::LoadOp::Load };
+        let mut color_attachments = Vec::with_capacity(2);
+        let depth_view = match target {
+            SpriteTarget::Scene => {
+                let scene_view = if lit { self.lighting.albedo_view() } else { self.post_processor.scene_view() };
+                let normal_view = self.lighting.normal_view();
+                // With MSAA the pass draws into the multisampled targets and resolves them into the usual ones at the end
+                let (scene_attachment, scene_resolve, normal_attachment, normal_resolve) = match &self.msaa_targets {
+                    Some(msaa_targets) => (&msaa_targets.color.view, Some(scene_view), &msaa_targets.normal.view, Some(normal_view)),
+                    None => (scene_view, None, normal_view, None),
+                };
+                color_attachments.push(wgpu::RenderPassColorAttachment {
+                    view: scene_attachment,
+                    resolve_target: scene_resolve,
+                    ops: wgpu::Operations { load: load(self.clear_color), store: true },
+                });
+                // Whatever isn't covered by a sprite gets lit as a flat surface
+                color_attachments.push(wgpu::RenderPassColorAttachment {
+                    view: normal_attachment,
+                    resolve_target: normal_resolve,
+                    ops: wgpu::Operations { load: load(wgpu::Color { r: 0.5, g: 0.5, b: 1.0, a: 1.0 }), store: true },
+                });
+                &self.depth_texture.view
+            },
+            SpriteTarget::Frame => {
+                color_attachments.push(wgpu::RenderPassColorAttachment {
+                    view: frame_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: true },
+                });
+                &self.frame_depth_texture.view
+            },
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &color_attachments,
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                // The frame's depth starts over with every pass
+                depth_ops: Some(wgpu::Operations {
+                    load: if clear || target == SpriteTarget::Frame { wgpu::LoadOp::Clear(1.0) } else { wgpu::LoadOp::Load },
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        let pipelines = match target {
+            SpriteTarget::Scene => &self.scene_pipelines,
+            SpriteTarget::Frame => &self.frame_pipelines,
+        };
+
+        self.render_stats.render_passes += 1;
+
+        // Render renderables - opaque ones first, then the translucent ones back to front on top of them
+        render_pass.set_vertex_buffer(0, self.quad_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+
+        render_pass.set_pipeline(&pipelines.opaque);
+        self.render_stats.pipeline_switches += 1;
+        for batch in batches.opaque.iter() {
+            let bind_group = self.textures[batch.texture_id].bind_group.as_ref().unwrap();
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.set_bind_group(1, self.textures[batch.normal_map_id].bind_group.as_ref().unwrap(), &[]);
+            render_pass.draw(0..UNIT_QUAD.len() as u32, batch.instances.clone());
+            self.render_stats.texture_binds += 2;
+            self.render_stats.draw_calls += 1;
+        }
+
+        // Only switch pipelines when the material changes between batches
+        let mut current_material: Option<Option<usize>> = None;
+        for batch in batches.transparent.iter() {
+            if current_material != Some(batch.material_id) {
+                match batch.material_id {
+                    Some(material_id) => render_pass.set_pipeline(self.materials[material_id].pipeline(target)),
+                    None => render_pass.set_pipeline(&pipelines.transparent),
+                }
+                current_material = Some(batch.material_id);
+                self.render_stats.pipeline_switches += 1;
+            }
+            let bind_group = self.textures[batch.texture_id].bind_group.as_ref().unwrap();
+            render_pass.set_bind_group(0, bind_group, &[]);
+            // Set 1 is the material's uniforms for materials and the normal map otherwise
+            match batch.material_id {
+                Some(material_id) => render_pass.set_bind_group(1, &self.materials[material_id].bind_group, &[]),
+                None => render_pass.set_bind_group(1, self.textures[batch.normal_map_id].bind_group.as_ref().unwrap(), &[]),
+            }
+            render_pass.draw(0..UNIT_QUAD.len() as u32, batch.instances.clone());
+            self.render_stats.texture_binds += 2;
+            self.render_stats.draw_calls += 1;
+        }
+    }
+    // Vector font text goes straight into the frame, bitmap font text is already part of the UI pass
+    fn text_pass(&mut self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, renderable_texts: &[&RenderableText], placed_texts: &[Vec<PlacedSegment>]) -> Result<(), RendererError> {
+        for (renderable_text, placed) in renderable_texts.iter().zip(placed_texts.iter()) {
+            for (offset, color) in text_copies(renderable_text) {
+                for segment in placed.iter() {
+                    let span = &renderable_text.spans[segment.span_index];
+                    let glyph_font_id = match self.font(span.font) {
+                        LoadedFont::Vector { glyph_font_id, .. } => *glyph_font_id,
+                        LoadedFont::Bitmap(_) => continue,
                     };
-                    buffers.push(
-                        
-                        self.device.create_buffer_init(
-                            &wgpu::util::BufferInitDescriptor {
-                                label: None,
-                                contents: bytemuck::cast_slice(&[
-                                    crate::texture::Vertex{position: [p1x, p1y, 0.0], tex_coords: [tex_x1, tex_y1]},
-                                    crate::texture::Vertex{position: [p1x, p2y, 0.0], tex_coords: [tex_x1, tex_y2]},
-                                    crate::texture::Vertex{position: [p2x, p2y, 0.0], tex_coords: [tex_x2, tex_y2]},
-                
-                                    crate::texture::Vertex{position: [p1x, p1y, 0.0], tex_coords: [tex_x1, tex_y1]},
-                                    crate::texture::Vertex{position: [p2x, p2y, 0.0], tex_coords: [tex_x2, tex_y2]},
-                                    crate::texture::Vertex{position: [p2x, p1y, 0.0], tex_coords: [tex_x2, tex_y1]},
-                
-                                ]),
-                                usage: wgpu::BufferUsage::VERTEX,
-                            }
-                        )
-                    );
+                    self.glyph_brush.queue(Section {
+                        screen_position: ((segment.position[0] + offset[0]).round(), (segment.position[1] + offset[1]).round()),
+                        layout: Layout::default_single_line(),
+                        text: vec![
+                            Text::new(&segment.text)
+                                .with_font_id(glyph_font_id)
+                                .with_color(color.unwrap_or(span.color))
+                                .with_scale(span.size)
+                        ],
+                        ..Section::default()
+                    });
                 }
             }
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[
-                    wgpu::RenderPassColorAttachment {
-                        view: &frame.view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(wgpu::Color {
-                                r: 0.0,
-                                g: 0.0,
-                                b: 0.0,
-                                a: 0.0,
-                            }),
-                            store: true,
+        }
+
+        self.glyph_brush.draw_queued(
+            &self.device, 
+            &mut self.staging_belt, 
+            encoder, 
+            view, 
+            self.size.width, 
+            self.size.height,
+        ).map_err(RendererError::Text)?;
+        // The brush keeps its own buffers and draws everything queued in one go
+        self.render_stats.render_passes += 1;
+        self.render_stats.draw_calls += 1;
+        Ok(())
+    }
+    // The frame's depth follows the window
+    fn prepare_frame_depth(&mut self) {
+        if self.frame_depth_texture.width != self.sc_desc.width || self.frame_depth_texture.height != self.sc_desc.height {
+            self.frame_depth_texture = Texture::create_depth_texture(&self.device, self.sc_desc.width, self.sc_desc.height, 1, "frame_depth_texture");
+        }
+    }
+    // Depth and the multisampled targets follow the scene size, which changes with the window and the render scale
+    fn prepare_scene_targets(&mut self, width: u32, height: u32) {
+        let msaa_ready = (self.msaa_samples > 1) == self.msaa_targets.is_some();
+        if self.depth_texture.width == width && self.depth_texture.height == height && msaa_ready {
+            return;
+        }
+        self.depth_texture = Texture::create_depth_texture(&self.device, width, height, self.msaa_samples, "depth_texture");
+        self.msaa_targets = if self.msaa_samples > 1 {
+            Some(MsaaTargets {
+                color: RenderTarget::new_multisampled(&self.device, width, height, self.sc_desc.format, self.msaa_samples, "msaa_color"),
+                normal: RenderTarget::new_multisampled(&self.device, width, height, NORMAL_FORMAT, self.msaa_samples, "msaa_normals"),
+            })
+        } else {
+            None
+        };
+    }
+    // Normalized size of the text once laid out, wrapping included
+    pub fn measure_text(&self, renderable_text: &RenderableText) -> [f32; 2] {
+        let layout = self.layout_text(renderable_text);
+        [layout.width() / self.size.width as f32, layout.height() / self.size.height as f32]
+    }
+    fn layout_text(&self, renderable_text: &RenderableText) -> TextLayout {
+        let max_width = renderable_text.max_width.map(|max_width| max_width * self.size.width as f32);
+
+        // Greedy word wrap over all spans, breaking only at spaces. A single word wider than max_width
+        // gets a line to itself and sticks out
+        let mut lines: Vec<TextLine> = Vec::new();
+        let mut line = TextLine::default();
+        for (span_index, span) in renderable_text.spans.iter().enumerate() {
+            let metrics = self.font_metrics(span);
+            for (paragraph_index, paragraph) in span.text.split('\n').enumerate() {
+                if paragraph_index > 0 {
+                    lines.push(line);
+                    line = TextLine::default();
+                }
+                line.fit(&metrics);
+                for (word_index, word) in paragraph.split(' ').enumerate() {
+                    let mut piece = if word_index > 0 { format!(" {}", word) } else { word.to_owned() };
+                    let mut piece_width = self.span_width(span, &piece);
+                    if let Some(max_width) = max_width {
+                        if word_index > 0 && line.width > 0. && line.width + piece_width > max_width {
+                            lines.push(line);
+                            line = TextLine::default();
+                            line.fit(&metrics);
+                            piece = word.to_owned();
+                            piece_width = self.span_width(span, &piece);
                         }
                     }
-                ],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: true,
-                    }),
-                    stencil_ops: None,
-                }),
-            });
+                    match line.segments.last_mut() {
+                        Some((last_span_index, segment)) if *last_span_index == span_index => segment.push_str(&piece),
+                        _ => line.segments.push((span_index, piece)),
+                    }
+                    line.width += piece_width;
+                }
+            }
+        }
+        lines.push(line);
 
-            render_pass.set_pipeline(&self.render_pipeline);
+        TextLayout { lines, line_spacing: renderable_text.line_spacing }
+    }
+    // Top left corner of every segment in window pixels. Segments on the same line share a baseline
+    fn place_text(&self, renderable_text: &RenderableText) -> Vec<PlacedSegment> {
+        let layout = self.layout_text(renderable_text);
+        let [anchor_x, anchor_y] = [
+            (renderable_text.anchor[0] + renderable_text.x) * self.size.width as f32,
+            (renderable_text.anchor[1] + renderable_text.y) * self.size.height as f32,
+        ];
+        let mut top = match renderable_text.v_align {
+            VerticalAlign::Top => anchor_y,
+            VerticalAlign::Center => anchor_y - layout.height() / 2.,
+            VerticalAlign::Bottom => anchor_y - layout.height(),
+        };
 
-            // Render renderables
-            {
-                for (i, renderable) in renderables.iter().enumerate() {
-                    if renderable.texture_id as usize > (self.textures.len() - 1) {
-                        println!("Wrong texture id {:?}. Can't render", renderable.texture_id);
-                    } else {
-                        let texture = self.textures.get(renderable.texture_id as usize).unwrap();
-                        let buffer = buffers.get(i).unwrap();
-                        let bind_group = texture.bind_group.as_ref().unwrap();
-                        render_pass.set_vertex_buffer(0, buffer.slice(..));
-                        render_pass.set_bind_group(0, bind_group, &[]);
-                        render_pass.draw(0..6, 0..1);
-                    }
-                }  
-            }     
-        }
-        // Render text
-        {
-            for renderable_text in renderable_texts {
-                self.glyph_brush.queue(Section {
-                    screen_position: (renderable_text.x * self.size.width as f32, renderable_text.y * self.size.height as f32),
-                    text: vec![
-                        Text::new(&renderable_text.text)
-                            .with_color(renderable_text.color)
-                            .with_scale(renderable_text.size)
-                    ],
-                    ..Section::default()
-                });
+        let mut placed = Vec::new();
+        for line in layout.lines {
+            let mut x = match renderable_text.h_align {
+                HorizontalAlign::Left => anchor_x,
+                HorizontalAlign::Center => anchor_x - line.width / 2.,
+                HorizontalAlign::Right => anchor_x - line.width,
+            };
+            for (span_index, text) in line.segments {
+                let span = &renderable_text.spans[span_index];
+                let y = top + line.ascent - self.font_metrics(span).ascent;
+                let width = self.span_width(span, &text);
+                placed.push(PlacedSegment { span_index, text, position: [x, y] });
+                x += width;
             }
-            
+            top += line.advance * layout.line_spacing;
+        }
+        placed
+    }
+    // Bitmap font glyphs as unlit UI sprites for a target of the given size in pixels
+    fn bitmap_text_quads(&self, renderable_text: &RenderableText, placed: &[PlacedSegment], target_size: [u32; 2]) -> Vec<Renderable> {
+        let mut quads = Vec::new();
+        for (offset, color) in text_copies(renderable_text) {
+            for segment in placed.iter() {
+                let span = &renderable_text.spans[segment.span_index];
+                if let LoadedFont::Bitmap(font) = self.font(span.font) {
+                    let position = [segment.position[0] + offset[0], segment.position[1] + offset[1]];
+                    quads.extend(bitmap_glyph_quads(font, &segment.text, position, font.scale(span.size), color.unwrap_or(span.color), target_size));
+                }
+            }
+        }
+        quads
+    }
+    fn font(&self, font_id: FontId) -> &LoadedFont {
+        self.fonts.get(font_id.0).unwrap_or(&self.fonts[0])
+    }
+    fn font_metrics(&self, span: &TextSpan) -> FontMetrics {
+        match self.font(span.font) {
+            LoadedFont::Vector { font, .. } => {
+                let font = font.as_scaled(span.size);
+                FontMetrics { ascent: font.ascent(), height: font.height(), advance: font.height() + font.line_gap() }
+            },
+            LoadedFont::Bitmap(font) => {
+                let scale = font.scale(span.size);
+                FontMetrics { ascent: font.base * scale, height: font.line_height * scale, advance: font.line_height * scale }
+            },
+        }
+    }
+    fn span_width(&self, span: &TextSpan, text: &str) -> f32 {
+        match self.font(span.font) {
+            LoadedFont::Vector { font, .. } => text_width(&font.as_scaled(span.size), text),
+            LoadedFont::Bitmap(font) => font.text_width(text, font.scale(span.size)),
+        }
+    }
+    // Available to text spans from then on. The built in Press Start 2P is FontId(0)
+    pub fn register_font(&mut self, font_path: &str) -> Result<FontId, RendererError> {
+        let font_error = |error: anyhow::Error| RendererError::Font { path: font_path.to_owned(), error };
+        let bytes = std::fs::read(font_path).map_err(|error| font_error(error.into()))?;
+        let font = ab_glyph::FontArc::try_from_vec(bytes).map_err(|error| font_error(anyhow::anyhow!("{}", error)))?;
+        let glyph_font_id = self.glyph_brush.add_font(font.clone());
+        self.fonts.push(LoadedFont::Vector { font, glyph_font_id });
+        Ok(FontId(self.fonts.len() - 1))
+    }
+    // AngelCode BMFont .fnt in the text format, with its pages next to it. Span sizes get rounded
+    // to whole multiples of the size the font was drawn at
+    pub fn register_bitmap_font(&mut self, font_path: &str) -> Result<FontId, RendererError> {
+        let mut font = BitmapFont::load(font_path).map_err(|error| RendererError::Font { path: font_path.to_owned(), error })?;
+        font.page_texture_ids = font.page_paths.iter()
+            .map(|page_path| self.register_texture(&page_path.to_string_lossy()))
+            .collect::<Result<Vec<usize>, RendererError>>()?;
+        self.fonts.push(LoadedFont::Bitmap(font));
+        Ok(FontId(self.fonts.len() - 1))
+    }
+    fn renderable_instance(&self, renderable: &Renderable, depth: f32) -> SpriteInstance {
+        // TODO: HOW TO RENDER RESOLUTIONS OTHER THAN 16:9??
+        // assume target resolution of 1280x720; transform in its pixels so rotating doesn't get squashed by the aspect ratio
+        let res_width = self.desired_res.width as f32;
+        let res_height = self.desired_res.height as f32;
+        let (x1, y1) = (renderable.p1[0] * res_width, renderable.p1[1] * res_height);
+        let (x2, y2) = {
+            if renderable.use_texture_size {
+                let texture = self.textures.get(renderable.texture_id as usize).unwrap();
+                (x1 + texture.width as f32, y1 + texture.height as f32)
+            } else {
+                (renderable.p2[0] * res_width, renderable.p2[1] * res_height)
+            }
+        };
+
+        // Scale and rotate the corners around the pivot, then go to clip space. The transform is affine,
+        // so three corners pin the quad down and the shader gets the fourth from them
+        let pivot_x = x1 + (x2 - x1) * renderable.pivot[0];
+        let pivot_y = y1 + (y2 - y1) * renderable.pivot[1];
+        let (sin, cos) = renderable.rotation.sin_cos();
+        let transform = |x: f32, y: f32| -> [f32; 2] {
+            let dx = (x - pivot_x) * renderable.scale[0];
+            let dy = (y - pivot_y) * renderable.scale[1];
+            let x = pivot_x + dx * cos - dy * sin;
+            let y = pivot_y + dx * sin + dy * cos;
+            [x / res_width * 2.0 - 1.0, -(y / res_height * 2.0 - 1.0)]
+        };
+        let (top_left, bottom_left, top_right) = (transform(x1, y1), transform(x1, y2), transform(x2, y1));
+
+        let [u1, v1, u2, v2] = renderable.tex_rect;
+        let (tex_x1, tex_x2) = if renderable.horiz_mirror { (u2, u1) } else { (u1, u2) };
+        let (tex_y1, tex_y2) = if renderable.vert_mirror { (v2, v1) } else { (v1, v2) };
+        let color = [renderable.tint[0], renderable.tint[1], renderable.tint[2], renderable.tint[3] * renderable.opacity];
+        let params = renderable.material_params;
 
-            self.glyph_brush.draw_queued(
-                &self.device, 
-                &mut self.staging_belt, 
-                &mut encoder, 
-                &frame.view, 
-                self.size.width, 
-                self.size.height,
-            ).expect("Drawing glyphs queued");
+        SpriteInstance {
+            origin: [top_left[0], top_left[1], depth],
+            axis_x: [top_right[0] - top_left[0], top_right[1] - top_left[1]],
+            axis_y: [bottom_left[0] - top_left[0], bottom_left[1] - top_left[1]],
+            tex_rect: [tex_x1, tex_y1, tex_x2, tex_y2],
+            color,
+            params,
         }
-        self.staging_belt.finish();
+    }
+    // Corners keep their size, the edges stretch along one axis and the middle along both. Panels smaller
+    // than their borders squash the borders instead
+    fn nine_slice_instances(&self, renderable: &Renderable, nine_slice: &NineSlice, depth: f32) -> Vec<SpriteInstance> {
+        let texture = &self.textures[renderable.texture_id];
+        let [left, top, right, bottom] = nine_slice.borders;
+        let [x1, y1] = renderable.p1;
+        let [x2, y2] = renderable.p2;
+        let [u1, v1, u2, v2] = renderable.tex_rect;
 
-        self.queue.submit(std::iter::once(encoder.finish()));
+        let border_width = (left + right) * nine_slice.border_scale / self.desired_res.width as f32;
+        let border_height = (top + bottom) * nine_slice.border_scale / self.desired_res.height as f32;
+        let squash_x = if border_width > x2 - x1 && border_width > 0. { (x2 - x1) / border_width } else { 1. };
+        let squash_y = if border_height > y2 - y1 && border_height > 0. { (y2 - y1) / border_height } else { 1. };
+        let to_screen_x = nine_slice.border_scale * squash_x / self.desired_res.width as f32;
+        let to_screen_y = nine_slice.border_scale * squash_y / self.desired_res.height as f32;
 
-        self.staging_belt_local_spawner
-            .spawn(self.staging_belt.recall())
-            .expect("Staging belt recall;");
+        let xs = [x1, x1 + left * to_screen_x, x2 - right * to_screen_x, x2];
+        let ys = [y1, y1 + top * to_screen_y, y2 - bottom * to_screen_y, y2];
+        let us = [u1, u1 + left / texture.width as f32, u2 - right / texture.width as f32, u2];
+        let vs = [v1, v1 + top / texture.height as f32, v2 - bottom / texture.height as f32, v2];
 
-        self.staging_belt_local_pool.run_until_stalled();
+        // Every piece rotates and scales around the pivot of the whole panel
+        let pivot = [x1 + (x2 - x1) * renderable.pivot[0], y1 + (y2 - y1) * renderable.pivot[1]];
+        let mut instances = Vec::with_capacity(9);
+        for row in 0..3 {
+            for column in 0..3 {
+                let (piece_x1, piece_x2, piece_y1, piece_y2) = (xs[column], xs[column + 1], ys[row], ys[row + 1]);
+                if piece_x2 <= piece_x1 || piece_y2 <= piece_y1 {
+                    continue;
+                }
+                let piece = Renderable {
+                    texture_id: renderable.texture_id,
+                    p1: [piece_x1, piece_y1],
+                    p2: [piece_x2, piece_y2],
+                    tint: renderable.tint,
+                    opacity: renderable.opacity,
+                    rotation: renderable.rotation,
+                    pivot: [(pivot[0] - piece_x1) / (piece_x2 - piece_x1), (pivot[1] - piece_y1) / (piece_y2 - piece_y1)],
+                    scale: renderable.scale,
+                    tex_rect: [us[column], vs[row], us[column + 1], vs[row + 1]],
+                    material_params: renderable.material_params,
+                    ..Default::default()
+                };
+                instances.push(self.renderable_instance(&piece, depth));
+            }
+        }
+        instances
     }
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         self.size = new_size;
         self.sc_desc.width = new_size.width;
         self.sc_desc.height = new_size.height;
-        self.depth_texture = Texture::create_depth_texture(&self.device, &self.sc_desc, "depth_texture");
-        self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+        self.recreate_swap_chain();
+    }
+    // A minimized window has no size and can't have a swap chain, it gets one again with the next resize
+    fn recreate_swap_chain(&mut self) {
+        if !self.is_minimized() {
+            self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+        }
+    }
+    fn is_minimized(&self) -> bool {
+        self.sc_desc.width == 0 || self.sc_desc.height == 0
+    }
+    // Use the sprite shaders from these files instead of the built in ones and rebuild the pipelines whenever they change
+    pub fn watch_shaders(&mut self, vertex_path: &str, fragment_path: &str) {
+        self.shader_watcher = Some(ShaderWatcher::new(vertex_path, fragment_path));
+        self.reload_shaders_if_changed();
+    }
+    fn reload_shaders_if_changed(&mut self) {
+        let shader_watcher = match &mut self.shader_watcher {
+            Some(shader_watcher) => shader_watcher,
+            None => return,
+        };
+        if !shader_watcher.poll_changed() {
+            return;
+        }
+
+        // Keep the last working pipelines around unless all the new ones got built without a single error
+        let error_scope = ValidationErrorScope::push(&self.device);
+        let pipelines = shader_watcher.load(&self.device).map(|(vs_module, fs_module)| {
+            let scene_pipelines = SpritePipelines::new(&self.device, &self.render_pipeline_layout, &vs_module, &fs_module, self.sc_desc.format, SpriteTarget::Scene, self.msaa_samples);
+            let frame_pipelines = SpritePipelines::new(&self.device, &self.render_pipeline_layout, &vs_module, &fs_module, self.sc_desc.format, SpriteTarget::Frame, 1);
+            (scene_pipelines, frame_pipelines)
+        });
+        let errors = error_scope.pop(&self.device);
+        match pipelines {
+            Ok((scene_pipelines, frame_pipelines)) if errors.is_empty() => {
+                self.scene_pipelines = scene_pipelines;
+                self.frame_pipelines = frame_pipelines;
+                println!("Shaders reloaded");
+            },
+            Ok(_) => {
+                for error in errors {
+                    println!("Shader reload failed, keeping the last working pipeline: {}", error);
+                }
+            },
+            Err(error) => {
+                println!("Shader reload failed, keeping the last working pipeline: {:?}", error);
+            },
+        }
+    }
+    // Fraction of the window size the world gets drawn at. The UI, text and debug shapes go into the frame after
+    // the post effects, so they stay sharp either way
+    pub fn set_render_scale(&mut self, render_scale: f32) {
+        self.render_scale = render_scale.clamp(MIN_RENDER_SCALE, 1.);
+    }
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+    // Takes effect right away, the swap chain gets recreated
+    pub fn set_vsync(&mut self, vsync: VSync) {
+        self.sc_desc.present_mode = vsync.present_mode();
+        self.recreate_swap_chain();
+    }
+    // None renders as fast as the present mode allows
+    pub fn set_target_fps(&mut self, target_fps: Option<u32>) {
+        self.frame_pacer.set_target_fps(target_fps);
+    }
+    // Passes a frame is drawn with, they can be taken out, switched off or moved around. World and UI sprites go
+    // into the frame when their pass writes Frame and into the scene otherwise, orders that lose something get printed
+    pub fn render_graph(&mut self) -> &mut RenderGraph {
+        &mut self.render_graph
+    }
+    // Shapes and text queued here get drawn over the next frame only
+    pub fn debug(&mut self) -> &mut DebugDraw {
+        &mut self.debug_draw
+    }
+    pub fn set_clear_color(&mut self, color: [f32; 4]) {
+        self.clear_color = wgpu::Color { r: color[0] as f64, g: color[1] as f64, b: color[2] as f64, a: color[3] as f64 };
+    }
+    pub fn toggle_post_effect(&mut self, effect: PostEffect) {
+        let settings = self.post_processor.settings_mut(effect);
+        settings.enabled = !settings.enabled;
+        println!("{:?} {}", effect, if settings.enabled { "on" } else { "off" });
     }
-    pub fn register_texture(&mut self, texture_path: &str) -> usize {
-        if let Ok(texture) = Texture::load(&self.device, &self.queue, texture_path, &self.texture_bind_group_layout) {
-            self.textures.push(texture);
-            self.textures.len() - 1
+    pub fn set_post_effect_enabled(&mut self, effect: PostEffect, enabled: bool) {
+        self.post_processor.settings_mut(effect).enabled = enabled;
+    }
+    pub fn set_post_effect_intensity(&mut self, effect: PostEffect, intensity: f32) {
+        self.post_processor.settings_mut(effect).intensity = intensity;
+    }
+    pub fn set_post_effect_params(&mut self, effect: PostEffect, params: [f32; 4]) {
+        self.post_processor.settings_mut(effect).params = params;
+    }
+    // 256x16 color grading LUT, the default is res/lut/warm.png
+    pub fn set_color_grading_lut(&mut self, lut_path: &str) {
+        if let Err(error) = self.post_processor.set_lut(&self.device, &self.queue, lut_path) {
+            println!("Couldn't load LUT: {:?}", error);
+        }
+    }
+    // Saves the next rendered frame as a PNG
+    pub fn take_screenshot(&mut self, path: &str) {
+        self.frame_capture.request_screenshot(path);
+    }
+    pub fn start_recording(&mut self, mode: RecordingMode) {
+        if let Err(error) = self.frame_capture.start_recording(&self.device, mode) {
+            println!("Couldn't start recording: {:?}", error);
+        }
+    }
+    pub fn stop_recording(&mut self) {
+        self.frame_capture.stop_recording(&self.device);
+    }
+    pub fn is_recording(&self) -> bool {
+        self.frame_capture.is_recording()
+    }
+    // Adds a sprite pipeline with a custom fragment shader; it gets the sprite texture at set 0 and the params (plus time) at set 1.
+    // Like the sprite shader it has to write the screen space normal to location 1, see src/materials/
+    pub fn register_material(&mut self, name: &str, fragment_shader: &wgpu::ShaderModuleDescriptor, params: [f32; 4]) -> usize {
+        let fs_module = self.device.create_shader_module(fragment_shader);
+        let pipeline = create_sprite_pipeline(&self.device, &self.material_pipeline_layout, &self.sprite_vs_module, &fs_module, self.sc_desc.format, SpriteTarget::Scene, self.msaa_samples, Some(ALPHA_BLENDING), false);
+        let frame_pipeline = create_sprite_pipeline(&self.device, &self.material_pipeline_layout, &self.sprite_vs_module, &fs_module, self.sc_desc.format, SpriteTarget::Frame, 1, Some(ALPHA_BLENDING), false);
+        self.materials.push(Material::new(&self.device, name, pipeline, frame_pipeline, &self.material_bind_group_layout, params));
+        self.materials.len() - 1
+    }
+    pub fn get_material_id(&self, name: &str) -> Option<usize> {
+        self.materials.iter().position(|material| material.name == name)
+    }
+    pub fn set_material_params(&mut self, material_id: usize, params: [f32; 4]) {
+        if let Some(material) = self.materials.get_mut(material_id) {
+            material.set_params(params);
         } else {
-            panic!("Couldn't register texture: ".to_string() + &texture_path.to_string());
-        }        
+            println!("Wrong material id {:?}. Can't set params", material_id);
+        }
+    }
+    // Nearest filtered and clamped, right for most pixel art sprites
+    pub fn register_texture(&mut self, texture_path: &str) -> Result<usize, RendererError> {
+        self.register_texture_with_options(texture_path, &TextureOptions::default())
+    }
+    // Repeats when sampled outside of 0..1, for backgrounds that tile forever
+    pub fn register_tiling_texture(&mut self, texture_path: &str) -> Result<usize, RendererError> {
+        self.register_texture_with_options(texture_path, &TextureOptions { address_mode: wgpu::AddressMode::Repeat, ..Default::default() })
+    }
+    // Normal maps hold directions rather than colors, so they're loaded without the sRGB conversion
+    pub fn register_normal_map(&mut self, texture_path: &str) -> Result<usize, RendererError> {
+        self.register_texture_with_options(texture_path, &TextureOptions { srgb: false, ..Default::default() })
+    }
+    pub fn register_texture_with_options(&mut self, texture_path: &str, options: &TextureOptions) -> Result<usize, RendererError> {
+        let texture = Texture::load(&self.device, &self.queue, texture_path, &self.texture_bind_group_layout, options)
+            .map_err(|error| RendererError::Texture { path: texture_path.to_owned(), error })?;
+        self.textures.push(texture);
+        Ok(self.textures.len() - 1)
+    }
+    // 1x1 white, for flat colored quads through the tint
+    pub fn white_texture(&self) -> usize {
+        self.white_texture
+    }
+    // 1x1 magenta, for using in place of a texture that failed to register
+    pub fn missing_texture(&self) -> usize {
+        self.missing_texture
+    }
+    // Lights beyond this many get dropped, furthest from the middle of the screen first
+    pub fn set_max_lights(&mut self, max_lights: usize) {
+        self.lighting.set_max_lights(max_lights);
+    }
+    fn register_image(&mut self, img: &image::DynamicImage, label: &str, options: &TextureOptions) -> Result<usize, RendererError> {
+        let texture = Texture::from_image(&self.device, &self.queue, img, Some(label), &self.texture_bind_group_layout, options)
+            .map_err(|error| RendererError::Texture { path: label.to_owned(), error })?;
+        self.textures.push(texture);
+        Ok(self.textures.len() - 1)
+    }
+}
+
+// Text broken into lines, sizes in pixels
+struct TextLayout {
+    lines: Vec<TextLine>,
+    line_spacing: f32,
+}
+
+#[derive(Default)]
+struct TextLine {
+    segments: Vec<(usize, String)>, // Index of the span the text came from
+    width: f32,
+    ascent: f32, // Top of the line to the shared baseline
+    height: f32, // Of the tallest font on the line
+    advance: f32, // Top of this line to the top of the next before line spacing
+}
+
+impl TextLine {
+    fn fit(&mut self, metrics: &FontMetrics) {
+        self.ascent = self.ascent.max(metrics.ascent);
+        self.height = self.height.max(metrics.height);
+        self.advance = self.advance.max(metrics.advance);
+    }
+}
+
+// Pixels, for a span's font at the span's size
+struct FontMetrics {
+    ascent: f32,
+    height: f32,
+    advance: f32,
+}
+
+struct PlacedSegment {
+    span_index: usize,
+    text: String,
+    position: [f32; 2],
+}
+
+// Vector fonts get rasterized by the glyph brush, bitmap fonts are drawn as sprites
+enum LoadedFont {
+    Vector { font: ab_glyph::FontArc, glyph_font_id: wgpu_glyph::FontId },
+    Bitmap(BitmapFont),
+}
+
+impl TextLayout {
+    fn width(&self) -> f32 {
+        self.lines.iter().map(|line| line.width).fold(0., f32::max)
+    }
+    fn height(&self) -> f32 {
+        match self.lines.split_last() {
+            Some((last, rest)) => rest.iter().map(|line| line.advance * self.line_spacing).sum::<f32>() + last.height,
+            None => 0.,
+        }
+    }
+}
+
+fn text_width<F: Font, SF: ScaleFont<F>>(font: &SF, text: &str) -> f32 {
+    let mut width = 0.;
+    let mut previous: Option<ab_glyph::GlyphId> = None;
+    for c in text.chars() {
+        let glyph_id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            width += font.kern(previous, glyph_id);
+        }
+        width += font.h_advance(glyph_id);
+        previous = Some(glyph_id);
+    }
+    width
+}
+
+// The font's pixels get scaled by whole numbers from a whole pixel starting point, so they line up with the target's exactly
+fn bitmap_glyph_quads(font: &BitmapFont, text: &str, position: [f32; 2], scale: f32, color: [f32; 4], [target_width, target_height]: [u32; 2]) -> Vec<Renderable> {
+    let (target_width, target_height) = (target_width as f32, target_height as f32);
+    let mut quads = Vec::new();
+    let mut pen_x = position[0].round();
+    let top = position[1].round();
+    let mut previous: Option<char> = None;
+    for c in text.chars() {
+        if let Some(previous) = previous {
+            pen_x += font.kerning(previous, c) * scale;
+        }
+        previous = Some(c);
+        let glyph = match font.glyph(c) {
+            Some(glyph) => glyph,
+            None => continue,
+        };
+        if glyph.width > 0. && glyph.height > 0. {
+            let x1 = pen_x + glyph.x_offset * scale;
+            let y1 = top + glyph.y_offset * scale;
+            quads.push(Renderable {
+                texture_id: font.page_texture_ids[glyph.page],
+                layer: RenderLayer::Ui,
+                p1: [x1 / target_width, y1 / target_height],
+                p2: [(x1 + glyph.width * scale) / target_width, (y1 + glyph.height * scale) / target_height],
+                tex_rect: [
+                    glyph.x / font.page_width,
+                    glyph.y / font.page_height,
+                    (glyph.x + glyph.width) / font.page_width,
+                    (glyph.y + glyph.height) / font.page_height,
+                ],
+                tint: color,
+                lit: false,
+                ..Default::default()
+            });
+        }
+        pen_x += glyph.x_advance * scale;
+    }
+    quads
+}
+
+// Everything queued later ends up on top, so shadow first, then the outline, then the text itself
+fn text_copies(renderable_text: &RenderableText) -> Vec<([f32; 2], Option<[f32; 4]>)> {
+    let mut copies: Vec<([f32; 2], Option<[f32; 4]>)> = Vec::new();
+    if let Some(shadow) = &renderable_text.shadow {
+        copies.push((shadow.offset, Some(shadow.color)));
+    }
+    if let Some(outline) = &renderable_text.outline {
+        for (dx, dy) in [(-1., -1.), (0., -1.), (1., -1.), (-1., 0.), (1., 0.), (-1., 1.), (0., 1.), (1., 1.)] {
+            copies.push(([dx * outline.thickness, dy * outline.thickness], Some(outline.color)));
+        }
+    }
+    copies.push(([0., 0.], None));
+    copies
+}
+
+enum SortedItem<'a> {
+    Sprite(&'a Renderable),
+    Particles(&'a RenderableParticles),
+    BitmapText(&'a [Renderable]), // All glyphs of one text, kept together so the outline stays under the letters
+}
+
+impl<'a> SortedItem<'a> {
+    fn layer(&self) -> RenderLayer {
+        match self {
+            SortedItem::Sprite(renderable) => renderable.layer,
+            SortedItem::Particles(particles) => particles.layer,
+            SortedItem::BitmapText(_) => RenderLayer::Ui,
+        }
+    }
+    fn z(&self) -> u32 {
+        match self {
+            SortedItem::Sprite(renderable) => renderable.z,
+            SortedItem::Particles(particles) => particles.z,
+            SortedItem::BitmapText(_) => u32::MAX,
+        }
+    }
+}
+
+// Where a sprite pass draws
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SpriteTarget {
+    Scene, // At the render scale with normals next to the colors, lit and post processed afterwards
+    Frame, // At full resolution after the post effects, colors only
+}
+
+struct SpritePipelines {
+    opaque: wgpu::RenderPipeline,
+    transparent: wgpu::RenderPipeline,
+}
+
+impl SpritePipelines {
+    fn new(device: &wgpu::Device, layout: &wgpu::PipelineLayout, vs_module: &wgpu::ShaderModule, fs_module: &wgpu::ShaderModule, format: wgpu::TextureFormat, target: SpriteTarget, sample_count: u32) -> Self {
+        // Opaque sprites write depth so anything drawn later behind them gets rejected, translucent ones only test against it
+        Self {
+            opaque: create_sprite_pipeline(device, layout, vs_module, fs_module, format, target, sample_count, None, true),
+            transparent: create_sprite_pipeline(device, layout, vs_module, fs_module, format, target, sample_count, Some(ALPHA_BLENDING), false),
+        }
+    }
+}
+
+// Draw calls of a single sprite pass, opaque ones go first in any order
+#[derive(Default)]
+struct PassBatches {
+    opaque: Vec<DrawBatch>,
+    transparent: Vec<DrawBatch>,
+}
+
+#[derive(Default)]
+struct SpriteBatches {
+    world: PassBatches,
+    ui: PassBatches,
+}
+
+// Consecutive sprites using the same material, texture and normal map that can go out in a single draw call
+struct DrawBatch {
+    material_id: Option<usize>,
+    texture_id: usize,
+    normal_map_id: usize,
+    instances: Range<u32>,
+}
+
+fn push_to_batches(batches: &mut Vec<DrawBatch>, material_id: Option<usize>, texture_id: usize, normal_map_id: usize, instances: Range<u32>) {
+    if let Some(last) = batches.last_mut() {
+        if last.material_id == material_id && last.texture_id == texture_id && last.normal_map_id == normal_map_id && last.instances.end == instances.start {
+            last.instances.end = instances.end;
+            return;
+        }
+    }
+    batches.push(DrawBatch { material_id, texture_id, normal_map_id, instances });
+}
+
+fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("sprite_instances"),
+        size: (capacity * mem::size_of::<SpriteInstance>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_sprite_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    vs_module: &wgpu::ShaderModule,
+    fs_module: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    target: SpriteTarget,
+    sample_count: u32,
+    blend: Option<wgpu::BlendState>,
+    depth_write_enabled: bool,
+) -> wgpu::RenderPipeline {
+    // The shaders always write normals, without a target for them they go nowhere
+    let color_target = wgpu::ColorTargetState { format, write_mask: wgpu::ColorWrite::ALL, blend };
+    let normal_target = wgpu::ColorTargetState { format: NORMAL_FORMAT, write_mask: wgpu::ColorWrite::ALL, blend };
+    let targets = match target {
+        SpriteTarget::Scene => vec![color_target, normal_target],
+        SpriteTarget::Frame => vec![color_target],
+    };
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor{
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: vs_module,
+            entry_point: "main",
+            buffers: &[
+                // Unit quad corners
+                wgpu::VertexBufferLayout {
+                    array_stride: mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    step_mode: wgpu::InputStepMode::Vertex,
+                    attributes: &[
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            shader_location: 0,
+                            format: wgpu::VertexFormat::Float32x2,
+                        },
+                    ],
+                },
+                // SpriteInstance
+                wgpu::VertexBufferLayout {
+                    array_stride: mem::size_of::<SpriteInstance>() as wgpu::BufferAddress,
+                    step_mode: wgpu::InputStepMode::Instance,
+                    attributes: &[
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            shader_location: 1,
+                            format: wgpu::VertexFormat::Float32x3,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                            shader_location: 2,
+                            format: wgpu::VertexFormat::Float32x2,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                            shader_location: 3,
+                            format: wgpu::VertexFormat::Float32x2,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: mem::size_of::<[f32; 7]>() as wgpu::BufferAddress,
+                            shader_location: 4,
+                            format: wgpu::VertexFormat::Float32x4,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: mem::size_of::<[f32; 11]>() as wgpu::BufferAddress,
+                            shader_location: 5,
+                            format: wgpu::VertexFormat::Float32x4,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: mem::size_of::<[f32; 15]>() as wgpu::BufferAddress,
+                            shader_location: 6,
+                            format: wgpu::VertexFormat::Float32x4,
+                        },
+                    ],
+                },
+            ],
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled,
+            bias: DepthBiasState::default(),
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+        }),
+        fragment: Some(wgpu::FragmentState {
+            module: fs_module,
+            entry_point: "main",
+            targets: &targets,
+        }),
+        multisample: MultisampleState {
+            alpha_to_coverage_enabled: false,
+            count: sample_count,
+            mask: !0,
+        },
+        primitive: PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            clamp_depth: false,
+            conservative: false,
+            // Negative scales flip the winding and sprites are never seen from behind anyway
+            cull_mode: None,
+            front_face: wgpu::FrontFace::Ccw,
+            strip_index_format: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+        },
+    })
+}
+
+// Scene sized scene and normal targets with several samples per pixel
+struct MsaaTargets {
+    color: RenderTarget,
+    normal: RenderTarget,
+}
+
+// Scene size for a render scale, never smaller than a pixel
+fn scaled_size(sc_desc: &wgpu::SwapChainDescriptor, render_scale: f32) -> [u32; 2] {
+    [
+        ((sc_desc.width as f32 * render_scale).round() as u32).max(1),
+        ((sc_desc.height as f32 * render_scale).round() as u32).max(1),
+    ]
+}
+
+// Sprite passes draw into whatever they say they write, the frame or the scene
+fn sprite_target(pass: &GraphPass) -> SpriteTarget {
+    if pass.writes.contains(&Attachment::Frame) {
+        SpriteTarget::Frame
+    } else {
+        SpriteTarget::Scene
+    }
+}
+
+// What a sprite pass draws into, only the scene shrinks with the render scale
+fn target_size(sc_desc: &wgpu::SwapChainDescriptor, render_scale: f32, target: SpriteTarget) -> [u32; 2] {
+    match target {
+        SpriteTarget::Scene => scaled_size(sc_desc, render_scale),
+        SpriteTarget::Frame => [sc_desc.width, sc_desc.height],
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VSync {
+    On, // Waits for the display, never tears
+    Off, // Tears, lowest latency
+    Mailbox, // Doesn't tear and doesn't wait either, newer frames replace queued ones. Falls back to On where unsupported
+}
+
+impl VSync {
+    fn present_mode(&self) -> wgpu::PresentMode {
+        match self {
+            VSync::On => wgpu::PresentMode::Fifo,
+            VSync::Off => wgpu::PresentMode::Immediate,
+            VSync::Mailbox => wgpu::PresentMode::Mailbox,
+        }
+    }
+}
+
+pub struct RendererConfig {
+    pub vsync: VSync,
+    pub target_fps: Option<u32>, // Frames get held back with a sleep to stay under it, on top of whatever vsync does
+    pub power_preference: wgpu::PowerPreference, // Which GPU to pick on machines with more than one
+    pub backends: wgpu::BackendBit,
+    pub format: Option<wgpu::TextureFormat>, // None goes with the surface's preferred format
+    pub msaa_samples: u32, // 1 turns it off, 4 is the only other count that works everywhere
+    pub render_scale: f32, // The world gets drawn at this fraction of the window size and stretched back up with big pixels
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        Self {
+            vsync: VSync::On,
+            target_fps: None,
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            backends: wgpu::BackendBit::PRIMARY,
+            format: None,
+            msaa_samples: 1,
+            render_scale: 1.,
+        }
+    }
+}
+
+// What can go wrong setting up the GPU or loading things onto it
+#[derive(Debug)]
+pub enum RendererError {
+    NoAdapter, // Nothing that can draw to the window with the asked for backends
+    RequestDevice(wgpu::RequestDeviceError),
+    Texture { path: String, error: anyhow::Error },
+    Font { path: String, error: anyhow::Error },
+    OutOfMemory, // The swap chain couldn't get a frame, nothing to do but give up
+    Text(String), // The glyph brush couldn't draw the queued text
+    StagingBelt(futures::task::SpawnError), // Its buffers couldn't be recalled for the next frame
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RendererError::NoAdapter => write!(f, "No graphics adapter can draw to this window"),
+            RendererError::RequestDevice(error) => write!(f, "Couldn't open the graphics device: {}", error),
+            RendererError::Texture { path, error } => write!(f, "Couldn't register texture {}: {:?}", path, error),
+            RendererError::Font { path, error } => write!(f, "Couldn't register font {}: {:?}", path, error),
+            RendererError::OutOfMemory => write!(f, "Out of GPU memory"),
+            RendererError::Text(error) => write!(f, "Couldn't draw text: {}", error),
+            RendererError::StagingBelt(error) => write!(f, "Couldn't recall the staging belt: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for RendererError {}
+
+// Drawn back to front in this order, ties broken by z and then by how low on screen the sprite reaches
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RenderLayer {
+    Background,
+    World,
+    Foreground,
+    Ui,
+}
+
 pub struct Renderable {
     pub texture_id: usize,
+    pub layer: RenderLayer,
+    pub z: u32,
     pub p1: [f32; 2],
     pub p2: [f32; 2],
     pub use_texture_size: bool,
     pub horiz_mirror: bool,
+    pub vert_mirror: bool,
+    pub tint: [f32; 4], // Multiplied with the texture color
+    pub opacity: f32,
+    pub rotation: f32, // Radians, clockwise on screen
+    pub pivot: [f32; 2], // Rotation and scale origin relative to the quad; [0.5, 0.5] is the center
+    pub scale: [f32; 2],
+    pub tex_rect: [f32; 4], // u1, v1, u2, v2 - going past 1 tiles textures registered as tiling
+    pub material_id: Option<usize>,
+    pub material_params: [f32; 4],
+    pub normal_map_id: Option<usize>, // Flat when there's none
+    pub lit: bool, // Unlit renderables keep their colors no matter the lights, for UI
+    pub nine_slice: Option<NineSlice>, // Stretched from p1 to p2 without distorting the corners. Ignores mirroring and use_texture_size
 }
 
-pub struct RenderableText {
+impl Default for Renderable {
+    fn default() -> Self {
+        Self {
+            texture_id: 0,
+            layer: RenderLayer::World,
+            z: 0,
+            p1: [0., 0.],
+            p2: [0., 0.],
+            use_texture_size: false,
+            horiz_mirror: false,
+            vert_mirror: false,
+            tint: [1., 1., 1., 1.],
+            opacity: 1.,
+            rotation: 0.,
+            pivot: [0.5, 0.5],
+            scale: [1., 1.],
+            tex_rect: [0., 0., 1., 1.],
+            material_id: None,
+            material_params: [0., 0., 0., 0.],
+            normal_map_id: None,
+            lit: true,
+            nine_slice: None,
+        }
+    }
+}
+
+// Which part of the texture is border, for panels that have to come in any size
+#[derive(Clone, Copy, Debug)]
+pub struct NineSlice {
+    pub borders: [f32; 4], // Left, top, right, bottom in texture pixels
+    pub border_scale: f32, // Screen pixels (at the desired resolution) per texture pixel in the borders
+}
+
+// All particles of one emitter, sorted as a single item and drawn with a single draw call
+pub struct RenderableParticles {
+    pub texture_id: usize,
+    pub layer: RenderLayer,
+    pub z: u32,
+    pub sort_y: f32,
+    pub particles: Vec<ParticleQuad>,
+}
+
+pub struct ParticleQuad {
+    pub center: [f32; 2],
+    pub size: [f32; 2],
+    pub color: [f32; 4],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HorizontalAlign {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VerticalAlign {
+    Top,
+    Center,
+    Bottom,
+}
+
+// Handed out by register_font and register_bitmap_font, either kind works in any span
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FontId(pub usize);
+
+#[derive(Clone)]
+pub struct TextSpan {
     pub text: String,
-    pub x: f32,
-    pub y: f32,
+    pub font: FontId,
     pub size: f32,
-    pub color: [f32; 4]
-}
\ No newline at end of file
+    pub color: [f32; 4],
+}
+
+impl Default for TextSpan {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            font: FontId(0),
+            size: 16.,
+            color: [1., 1., 1., 1.],
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TextOutline {
+    pub color: [f32; 4],
+    pub thickness: f32, // Pixels
+}
+
+#[derive(Clone)]
+pub struct TextShadow {
+    pub color: [f32; 4],
+    pub offset: [f32; 2], // Pixels
+}
+
+pub struct RenderableText {
+    pub spans: Vec<TextSpan>, // Laid out one after another, each with its own style
+    pub x: f32, // Offset from the anchor
+    pub y: f32,
+    pub anchor: [f32; 2], // Point on the screen x and y are relative to; [1., 1.] is the bottom right corner
+    pub h_align: HorizontalAlign, // Which side of the text ends up at x
+    pub v_align: VerticalAlign,
+    pub max_width: Option<f32>, // Normalized, longer lines get wrapped between words
+    pub line_spacing: f32, // Multiplies the font's line height
+    pub outline: Option<TextOutline>,
+    pub shadow: Option<TextShadow>,
+}
+
+impl Default for RenderableText {
+    fn default() -> Self {
+        Self {
+            spans: Vec::new(),
+            x: 0.,
+            y: 0.,
+            anchor: [0., 0.],
+            h_align: HorizontalAlign::Left,
+            v_align: VerticalAlign::Top,
+            max_width: None,
+            line_spacing: 1.,
+            outline: None,
+            shadow: None,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    const FONT: &str = "info size=8
+common lineHeight=8 base=7 scaleW=64 scaleH=64
+page id=0 file=\"font.png\"
+char id=65 x=0 y=0 width=5 height=7 xoffset=0 yoffset=1 xadvance=6 page=0
+char id=86 x=6 y=0 width=5 height=7 xoffset=1 yoffset=1 xadvance=6 page=0
+kerning first=65 second=86 amount=-1
+";
+
+    fn swap_chain_descriptor(width: u32, height: u32) -> wgpu::SwapChainDescriptor {
+        wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Bgra8UnormSrgb,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+        }
+    }
+
+    #[test]
+    fn sprite_passes_draw_where_the_graph_says() {
+        let mut graph = RenderGraph::default();
+        let targets: Vec<(PassKind, SpriteTarget)> = graph.order().iter()
+            .filter(|pass| pass.kind == PassKind::World || pass.kind == PassKind::Ui)
+            .map(|pass| (pass.kind, sprite_target(pass)))
+            .collect();
+        assert_eq!(targets, vec![(PassKind::World, SpriteTarget::Scene), (PassKind::Ui, SpriteTarget::Frame)]);
+    }
+
+    #[test]
+    fn bitmap_glyphs_land_on_whole_frame_pixels_at_half_render_scale() {
+        let mut font = BitmapFont::parse(FONT, Path::new("")).unwrap();
+        font.page_texture_ids = vec![0];
+        let sc_desc = swap_chain_descriptor(1280, 720);
+        let [width, height] = target_size(&sc_desc, 0.5, SpriteTarget::Frame);
+        assert_eq!([width, height], [1280, 720]);
+
+        let quads = bitmap_glyph_quads(&font, "AVA", [100.3, 50.6], font.scale(24.), [1.; 4], [width, height]);
+        assert_eq!(quads.len(), 3);
+        for quad in quads.iter() {
+            for [x, y] in [quad.p1, quad.p2] {
+                let (x, y) = (x * width as f32, y * height as f32);
+                assert!((x - x.round()).abs() < 1e-3 && (y - y.round()).abs() < 1e-3, "{} {} is between pixels", x, y);
+            }
+        }
+        // 3 times the font's size, starting from the rounded position
+        assert_eq!(quads[0].p1[0] * width as f32, 100.);
+        assert_eq!(quads[0].p2[1] * height as f32, 51. + 3. * 8.);
+    }
+}
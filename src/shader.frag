@@ -2,13 +2,31 @@
 
 layout(location=0) in vec3 v_position;
 layout(location=1) in vec2 v_tex_coords;
+layout(location=2) in vec4 v_color;
 
 layout(set = 0, binding = 0) uniform texture2D t_diffuse;
 layout(set = 0, binding = 1) uniform sampler s_diffuse;
 
+// Flat or unlit 1x1 textures for sprites without a normal map, alpha says whether lights affect the sprite
+layout(set = 1, binding = 0) uniform texture2D t_normal;
+layout(set = 1, binding = 1) uniform sampler s_normal;
+
 layout(location=0) out vec4 f_color;
+layout(location=1) out vec4 f_normal;
 
 void main() {
     vec4 object_color = texture(sampler2D(t_diffuse, s_diffuse), v_tex_coords);
-    f_color = object_color;
+    f_color = object_color * v_color;
+
+    vec4 normal_sample = texture(sampler2D(t_normal, s_normal), v_tex_coords);
+    vec2 normal = normal_sample.xy * 2.0 - 1.0;
+    // Normal maps point green up while the screen goes down, and mirrored sprites need their normals mirrored too
+    normal.y = -normal.y;
+    if (dFdx(v_tex_coords.x) < 0.0) {
+        normal.x = -normal.x;
+    }
+    if (dFdy(v_tex_coords.y) < 0.0) {
+        normal.y = -normal.y;
+    }
+    f_normal = vec4(normal * 0.5 + 0.5, normal_sample.a, f_color.a);
 }
\ No newline at end of file
@@ -1,14 +1,26 @@
 #version 450
 
-layout(location=0) in vec3 a_position;
-layout(location=1) in vec2 a_tex_coords;
+// Corner of the shared unit quad, 0 to 1 on both axes
+layout(location=0) in vec2 a_corner;
+
+// Per sprite, see SpriteInstance
+layout(location=1) in vec3 a_origin; // Clip space top left corner, z is the depth from the sort order
+layout(location=2) in vec2 a_axis_x; // Top left to top right corner
+layout(location=3) in vec2 a_axis_y; // Top left to bottom left corner
+layout(location=4) in vec4 a_tex_rect; // u1, v1, u2, v2 - swapped around for mirrored sprites
+layout(location=5) in vec4 a_color;
+layout(location=6) in vec4 a_params;
 
 layout(location=0) out vec3 v_position;
 layout(location=1) out vec2 v_tex_coords;
+layout(location=2) out vec4 v_color;
+layout(location=3) out vec4 v_params;
 
 void main() {
-    v_position = a_position;
-    v_tex_coords = a_tex_coords;
+    v_position = vec3(a_origin.xy + a_axis_x * a_corner.x + a_axis_y * a_corner.y, a_origin.z);
+    v_tex_coords = mix(a_tex_rect.xy, a_tex_rect.zw, a_corner);
+    v_color = a_color;
+    v_params = a_params;
 
-    gl_Position = vec4(a_position, 1.0);
+    gl_Position = vec4(v_position, 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,126 @@
+use anyhow::{anyhow, Context, Result};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+// No need to hit the file system every single frame
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+// Loads the sprite shaders from disk instead of the SPIR-V baked in by build.rs and notices when they change.
+// The language is picked by extension: .wgsl, .spv or GLSL for anything else (compiled with shaderc).
+// Both stages use "main" as their entry point.
+pub struct ShaderWatcher {
+    vertex_path: PathBuf,
+    fragment_path: PathBuf,
+    last_modified: [Option<SystemTime>; 2],
+    last_check: Option<Instant>,
+    compiler: Option<shaderc::Compiler>,
+}
+
+impl ShaderWatcher {
+    pub fn new(vertex_path: &str, fragment_path: &str) -> Self {
+        Self {
+            vertex_path: PathBuf::from(vertex_path),
+            fragment_path: PathBuf::from(fragment_path),
+            last_modified: [None, None],
+            last_check: None,
+            compiler: shaderc::Compiler::new(),
+        }
+    }
+
+    // True the first time and whenever either file changed since the last call
+    pub fn poll_changed(&mut self) -> bool {
+        if let Some(last_check) = self.last_check {
+            if last_check.elapsed() < CHECK_INTERVAL {
+                return false;
+            }
+        }
+        self.last_check = Some(Instant::now());
+
+        let modified = [modified_time(&self.vertex_path), modified_time(&self.fragment_path)];
+        if modified == self.last_modified {
+            return false;
+        }
+        self.last_modified = modified;
+        true
+    }
+
+    pub fn load(&mut self, device: &wgpu::Device) -> Result<(wgpu::ShaderModule, wgpu::ShaderModule)> {
+        let vertex_path = self.vertex_path.clone();
+        let fragment_path = self.fragment_path.clone();
+        let vs_module = self.load_module(device, &vertex_path, shaderc::ShaderKind::Vertex)?;
+        let fs_module = self.load_module(device, &fragment_path, shaderc::ShaderKind::Fragment)?;
+        Ok((vs_module, fs_module))
+    }
+
+    fn load_module(&mut self, device: &wgpu::Device, path: &Path, kind: shaderc::ShaderKind) -> Result<wgpu::ShaderModule> {
+        let label = path.to_str();
+        let source = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("wgsl") => {
+                let src = std::fs::read_to_string(path).with_context(|| format!("Reading {}", path.display()))?;
+                // Validate here first, wgpu would just panic on a broken WGSL module
+                let module = naga::front::wgsl::parse_str(&src).map_err(|error| anyhow!(error.emit_to_string(&src)))?;
+                naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+                    .validate(&module)
+                    .map_err(|error| anyhow!("{}: {:?}", path.display(), error))?;
+                wgpu::ShaderSource::Wgsl(Cow::Owned(src))
+            },
+            Some("spv") => {
+                let bytes = std::fs::read(path).with_context(|| format!("Reading {}", path.display()))?;
+                if bytes.len() % 4 != 0 {
+                    return Err(anyhow!("{} is not valid SPIR-V", path.display()));
+                }
+                let words = bytes.chunks(4).map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]])).collect();
+                wgpu::ShaderSource::SpirV(Cow::Owned(words))
+            },
+            _ => {
+                let src = std::fs::read_to_string(path).with_context(|| format!("Reading {}", path.display()))?;
+                let compiler = self.compiler.as_mut().context("Unable to create shader compiler")?;
+                let compiled = compiler.compile_into_spirv(&src, kind, label.unwrap_or("shader"), "main", None)?;
+                wgpu::ShaderSource::SpirV(Cow::Owned(compiled.as_binary().to_vec()))
+            },
+        };
+
+        Ok(device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label,
+            source,
+            flags: wgpu::ShaderFlags::VALIDATION,
+        }))
+    }
+}
+
+// Collects the validation errors wgpu reports between push and pop instead of panicking on them, so a broken shader
+// doesn't kill the game. wgpu 0.9 doesn't have push_error_scope/pop_error_scope yet, but it reports errors right
+// away on the thread that made the call, so handing it a collecting handler for just those calls does the same
+pub struct ValidationErrorScope {
+    errors: Arc<Mutex<Vec<String>>>,
+}
+
+impl ValidationErrorScope {
+    pub fn push(device: &wgpu::Device) -> Self {
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let handler_errors = errors.clone();
+        device.on_uncaptured_error(move |error| match error {
+            wgpu::Error::ValidationError { description, .. } => handler_errors.lock().unwrap().push(description),
+            error => fatal_error_handler(error),
+        });
+        Self { errors }
+    }
+
+    // Puts back the usual handler for everything after, and returns what got reported in the meantime
+    pub fn pop(self, device: &wgpu::Device) -> Vec<String> {
+        device.on_uncaptured_error(fatal_error_handler);
+        let mut errors = self.errors.lock().unwrap();
+        errors.drain(..).collect()
+    }
+}
+
+// Same as wgpu's default handler, which can't be set again once replaced
+fn fatal_error_handler(error: wgpu::Error) {
+    panic!("wgpu error: {}", error);
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
@@ -0,0 +1,181 @@
+use crate::components::{Position, RigidBody};
+use std::collections::HashMap;
+
+// A bit more than a terrain tile, so most boxes sit in one to four cells
+const DEFAULT_CELL_SIZE: f32 = 0.1;
+
+// Uniform grid over the normalized world, only the cells something is in exist. Entities are stored by index
+// in every cell their box touches, so looking up a box only has to go through what's nearby
+pub struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl Default for SpatialHash {
+    fn default() -> Self {
+        Self::new(DEFAULT_CELL_SIZE)
+    }
+}
+
+impl SpatialHash {
+    pub fn new(cell_size: f32) -> Self {
+        Self { cell_size, cells: HashMap::new() }
+    }
+
+    // Starts over with every entity that has a rigid body. Cells that are still in use keep their allocations
+    pub fn rebuild(&mut self, all_positions: &Vec<Option<Position>>, all_rigid_bodies: &Vec<Option<RigidBody>>) {
+        for entities in self.cells.values_mut() {
+            entities.clear();
+        }
+        let iter = all_positions.iter().zip(all_rigid_bodies.iter()).enumerate();
+        for (index, position, rigid_body) in iter.filter_map(|(index, (position, rigid_body))| Some((index, position.as_ref()?, rigid_body.as_ref()?))) {
+            self.insert(index, [position.x, position.y], [position.x + rigid_body.width, position.y + rigid_body.height]);
+        }
+        self.cells.retain(|_, entities| !entities.is_empty());
+    }
+
+    pub fn insert(&mut self, index: usize, min: [f32; 2], max: [f32; 2]) {
+        for cell in self.cells_between(min, max) {
+            self.cells.entry(cell).or_default().push(index);
+        }
+    }
+
+    // Has to get the same box the entity was inserted with
+    pub fn remove(&mut self, index: usize, min: [f32; 2], max: [f32; 2]) {
+        for cell in self.cells_between(min, max) {
+            if let Some(entities) = self.cells.get_mut(&cell) {
+                entities.retain(|entity| *entity != index);
+            }
+        }
+    }
+
+    // Entities whose cells overlap the box, each once and in index order. They still need a proper overlap test
+    pub fn query(&self, min: [f32; 2], max: [f32; 2]) -> Vec<usize> {
+        let mut found: Vec<usize> = self.cells_between(min, max)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flat_map(|entities| entities.iter().copied())
+            .collect();
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+
+    fn cells_between(&self, min: [f32; 2], max: [f32; 2]) -> impl Iterator<Item = (i32, i32)> {
+        let [min_x, min_y] = self.cell_at(min);
+        let [max_x, max_y] = self.cell_at(max);
+        (min_x..=max_x).flat_map(move |x| (min_y..=max_y).map(move |y| (x, y)))
+    }
+
+    fn cell_at(&self, point: [f32; 2]) -> [i32; 2] {
+        [(point[0] / self.cell_size).floor() as i32, (point[1] / self.cell_size).floor() as i32]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(spatial_hash: &SpatialHash, min: [f32; 2], max: [f32; 2]) -> Vec<(i32, i32)> {
+        let mut cells: Vec<(i32, i32)> = spatial_hash.cells_between(min, max).collect();
+        cells.sort_unstable();
+        cells
+    }
+
+    #[test]
+    fn cells_between_covers_every_cell_the_box_touches() {
+        let spatial_hash = SpatialHash::new(1.);
+        assert_eq!(cells(&spatial_hash, [0.25, 0.25], [0.75, 0.75]), vec![(0, 0)]);
+        assert_eq!(cells(&spatial_hash, [0.5, 0.5], [2.5, 1.5]), vec![(0, 0), (0, 1), (1, 0), (1, 1), (2, 0), (2, 1)]);
+    }
+
+    #[test]
+    fn cells_between_handles_negative_coordinates() {
+        let spatial_hash = SpatialHash::new(1.);
+        assert_eq!(cells(&spatial_hash, [-1.5, -0.5], [-1.25, -0.25]), vec![(-2, -1)]);
+        assert_eq!(cells(&spatial_hash, [-0.5, -0.5], [0.5, 0.5]), vec![(-1, -1), (-1, 0), (0, -1), (0, 0)]);
+    }
+
+    #[test]
+    fn cells_between_counts_a_box_on_a_cell_boundary_in_both_cells() {
+        let spatial_hash = SpatialHash::new(1.);
+        // Touching boxes have to share a cell, or the one on the other side of the edge would never be looked at
+        assert_eq!(cells(&spatial_hash, [1., 1.], [2., 2.]), vec![(1, 1), (1, 2), (2, 1), (2, 2)]);
+        assert_eq!(cells(&spatial_hash, [0.5, 0.5], [1., 0.75]), vec![(0, 0), (1, 0)]);
+    }
+
+    #[test]
+    fn query_finds_inserted_boxes_nearby_only() {
+        let mut spatial_hash = SpatialHash::new(1.);
+        spatial_hash.insert(0, [0.25, 0.25], [0.5, 0.5]);
+        spatial_hash.insert(1, [5.25, 5.25], [5.5, 5.5]);
+
+        assert_eq!(spatial_hash.query([0., 0.], [0.75, 0.75]), vec![0]);
+        assert_eq!(spatial_hash.query([5., 5.], [6.5, 6.5]), vec![1]);
+        assert!(spatial_hash.query([2.5, 2.5], [3.5, 3.5]).is_empty());
+    }
+
+    #[test]
+    fn query_returns_a_box_spanning_several_cells_once() {
+        let mut spatial_hash = SpatialHash::new(1.);
+        spatial_hash.insert(3, [0.5, 0.5], [2.5, 2.5]);
+        spatial_hash.insert(1, [1.25, 1.25], [1.5, 1.5]);
+
+        assert_eq!(spatial_hash.query([0., 0.], [3., 3.]), vec![1, 3]);
+        // Every cell it covers knows about it
+        assert_eq!(spatial_hash.query([0.75, 2.25], [0.75, 2.25]), vec![3]);
+        assert_eq!(spatial_hash.query([2.25, 0.75], [2.25, 0.75]), vec![3]);
+    }
+
+    #[test]
+    fn query_finds_boxes_at_negative_coordinates() {
+        let mut spatial_hash = SpatialHash::new(1.);
+        spatial_hash.insert(0, [-1.5, -1.5], [-0.5, -0.5]);
+        spatial_hash.insert(1, [0.25, 0.25], [0.5, 0.5]);
+
+        assert_eq!(spatial_hash.query([-2., -2.], [-1.25, -1.25]), vec![0]);
+        assert_eq!(spatial_hash.query([-0.25, -0.25], [0.25, 0.25]), vec![0, 1]);
+    }
+
+    #[test]
+    fn query_finds_boxes_touching_on_a_cell_boundary() {
+        let mut spatial_hash = SpatialHash::new(1.);
+        spatial_hash.insert(0, [0.5, 0.5], [1., 1.]);
+
+        assert_eq!(spatial_hash.query([1., 0.5], [1.5, 1.]), vec![0]);
+    }
+
+    #[test]
+    fn remove_takes_out_only_that_entity() {
+        let mut spatial_hash = SpatialHash::new(1.);
+        spatial_hash.insert(0, [0.5, 0.5], [1.5, 1.5]);
+        spatial_hash.insert(1, [0.75, 0.75], [1.25, 1.25]);
+
+        spatial_hash.remove(0, [0.5, 0.5], [1.5, 1.5]);
+
+        assert_eq!(spatial_hash.query([0., 0.], [2., 2.]), vec![1]);
+        spatial_hash.remove(1, [0.75, 0.75], [1.25, 1.25]);
+        assert!(spatial_hash.query([0., 0.], [2., 2.]).is_empty());
+    }
+
+    #[test]
+    fn rebuild_replaces_everything_with_the_rigid_bodies() {
+        let mut spatial_hash = SpatialHash::new(1.);
+        spatial_hash.insert(7, [10.25, 10.25], [10.5, 10.5]);
+
+        let positions = vec![Some(Position {x: 0.25, y: 0.25}), Some(Position {x: -1.5, y: 2.5}), None, Some(Position {x: 0.5, y: 0.5})];
+        let rigid_bodies = vec![
+            Some(RigidBody {width: 0.25, height: 0.25}),
+            Some(RigidBody {width: 2., height: 0.25}),
+            Some(RigidBody {width: 1., height: 1.}),
+            None,
+        ];
+        spatial_hash.rebuild(&positions, &rigid_bodies);
+
+        // What was there before is gone, along with its cell
+        assert!(spatial_hash.query([10., 10.], [11., 11.]).is_empty());
+        assert!(!spatial_hash.cells.contains_key(&(10, 10)));
+        // Entities missing either component get skipped
+        assert_eq!(spatial_hash.query([0., 0.], [1., 1.]), vec![0]);
+        assert_eq!(spatial_hash.query([-1.75, 2.25], [0.75, 2.75]), vec![1]);
+    }
+}
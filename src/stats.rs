@@ -0,0 +1,121 @@
+use crate::debug_draw::DebugDraw;
+use crate::frame_pacer::FrameStats;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Frames the graph covers, four seconds or so at 60 FPS
+const GRAPH_LENGTH: usize = 240;
+// Top of the graph, longer frames get clipped to it
+const GRAPH_MAX_MS: f32 = 50.;
+// Where the overlay sits, top right corner and size in normalized screen coordinates
+const OVERLAY_POSITION: [f32; 2] = [0.7, 0.02];
+const GRAPH_SIZE: [f32; 2] = [0.28, 0.15];
+const LINE_HEIGHT: f32 = 0.018;
+
+// What the GPU got asked to do during the last Renderer::render, frame captures included
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderStats {
+    pub render_passes: u32,
+    pub draw_calls: u32,
+    pub instances: u32, // Sprites, each one a six vertex quad
+    pub vertices: u32,
+    pub texture_binds: u32, // Bind group changes, whatever is in the group
+    pub pipeline_switches: u32,
+    pub buffer_allocations: u32,
+}
+
+impl RenderStats {
+    // One pass of a single fullscreen triangle, which is what the lighting and every post effect boil down to
+    pub fn fullscreen_pass(&mut self) {
+        self.render_passes += 1;
+        self.pipeline_switches += 1;
+        self.texture_binds += 1;
+        self.draw_calls += 1;
+        self.vertices += 3;
+    }
+}
+
+// Collects everything worth knowing about the current frame and draws it when asked to
+#[derive(Default)]
+pub struct Stats {
+    frame: FrameStats,
+    render: RenderStats, // Of the previous frame, this one hasn't been rendered yet
+    entity_count: usize,
+    systems: Vec<(&'static str, f32)>, // In the order they ran, milliseconds
+    lap_start: Option<Instant>,
+    frame_times: VecDeque<f32>,
+}
+
+impl Stats {
+    pub fn begin_frame(&mut self, frame: &FrameStats, render: &RenderStats, entity_count: usize) {
+        self.frame = *frame;
+        self.render = *render;
+        self.entity_count = entity_count;
+        self.systems.clear();
+        self.lap_start = Some(Instant::now());
+        if self.frame_times.len() == GRAPH_LENGTH {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame.frame_time);
+    }
+
+    // Books everything since the previous lap (or begin_frame) under name
+    pub fn lap(&mut self, name: &'static str) {
+        let now = Instant::now();
+        if let Some(lap_start) = self.lap_start {
+            self.record_system(name, now - lap_start);
+        }
+        self.lap_start = Some(now);
+    }
+
+    pub fn record_system(&mut self, name: &'static str, time: Duration) {
+        let time = time.as_secs_f32() * 1000.;
+        match self.systems.iter_mut().find(|(system, _)| *system == name) {
+            Some((_, total)) => *total += time,
+            None => self.systems.push((name, time)),
+        }
+    }
+
+    // Milliseconds booked under name this frame so far
+    pub fn system_time(&self, name: &str) -> f32 {
+        self.systems.iter().find(|(system, _)| *system == name).map(|(_, time)| *time).unwrap_or(0.)
+    }
+
+    // Frame time graph with 60 and 30 FPS marks, numbers underneath
+    pub fn draw_overlay(&self, debug: &mut DebugDraw) {
+        let [left, top] = OVERLAY_POSITION;
+        let [width, height] = GRAPH_SIZE;
+        let bottom = top + height;
+        let y_for = |ms: f32| bottom - ms.min(GRAPH_MAX_MS) / GRAPH_MAX_MS * height;
+
+        debug.rect([left, top], [left + width, bottom], [1., 1., 1., 0.5]);
+        for (ms, color) in [(1000. / 60., [0., 1., 0., 0.5]), (1000. / 30., [1., 1., 0., 0.5])] {
+            debug.line([left, y_for(ms)], [left + width, y_for(ms)], color);
+        }
+        let step = width / (GRAPH_LENGTH - 1) as f32;
+        let points: Vec<[f32; 2]> = self.frame_times.iter().enumerate()
+            .map(|(i, ms)| [left + (i + GRAPH_LENGTH - self.frame_times.len()) as f32 * step, y_for(*ms)])
+            .collect();
+        for pair in points.windows(2) {
+            debug.line(pair[0], pair[1], [1., 1., 1., 1.]);
+        }
+
+        let frame = &self.frame;
+        let render = &self.render;
+        let mut lines = vec![
+            format!("{:.0} fps  {:.1} ms  avg {:.1}  worst {:.1}", frame.fps, frame.frame_time, frame.average_frame_time, frame.worst_frame_time),
+            format!("render {:.2} ms  sleep {:.2} ms", frame.render_time, frame.sleep_time),
+            format!("{} draws  {} passes  {} sprites  {} vertices", render.draw_calls, render.render_passes, render.instances, render.vertices),
+            format!("{} binds  {} pipelines  {} buffers", render.texture_binds, render.pipeline_switches, render.buffer_allocations),
+            format!("{} entities", self.entity_count),
+        ];
+        let update_time: f32 = self.systems.iter().map(|(_, ms)| ms).sum();
+        lines.push(format!("update {:.2} ms", update_time));
+        for (name, ms) in self.systems.iter() {
+            lines.push(format!("  {:<16} {:.3}", name, ms));
+        }
+        for (i, line) in lines.iter().enumerate() {
+            debug.text([left, bottom + 0.01 + i as f32 * LINE_HEIGHT], line, [1., 1., 1., 1.]);
+        }
+    }
+}
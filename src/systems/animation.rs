@@ -4,7 +4,7 @@ use crate::components::*;
 pub fn animation_system(
     animation_component_vector_opt: &mut Option<RefMut<Vec<Option<Animation>>>>, 
     animation_map_component_vector_opt: &mut Option<RefMut<Vec<Option<AnimationMap>>>>,
-    time_passed: u128
+    time_passed: f32
 ) {
     // Simple animations
     {
@@ -12,9 +12,9 @@ pub fn animation_system(
             let iterator = animation_component_vector.iter_mut().filter_map(|animation| Some(animation.as_mut()?));
             for animation in iterator {
                 if animation.running && !animation.sprites.is_empty() {
-                    animation.time_since_last_frame += time_passed as u32;
-                    if animation.time_since_last_frame >= animation.time_per_frame_ms {
-                        animation.time_since_last_frame -= animation.time_per_frame_ms;
+                    animation.time_since_last_frame += time_passed;
+                    if animation.time_since_last_frame >= animation.time_per_frame_ms as f32 {
+                        animation.time_since_last_frame -= animation.time_per_frame_ms as f32;
                         animation.current_frame_index += 1;
                         if animation.current_frame_index >= animation.sprites.len() {
                             animation.current_frame_index = 0;
@@ -32,9 +32,9 @@ pub fn animation_system(
             for animation_map in iterator {
                 if let Some(mut animation) = animation_map.map.get_mut(animation_map.current_animation_name) {
                     if animation.running && !animation.sprites.is_empty() {
-                        animation.time_since_last_frame += time_passed as u32;
-                        if animation.time_since_last_frame >= animation.time_per_frame_ms {
-                            animation.time_since_last_frame -= animation.time_per_frame_ms;
+                        animation.time_since_last_frame += time_passed;
+                        if animation.time_since_last_frame >= animation.time_per_frame_ms as f32 {
+                            animation.time_since_last_frame -= animation.time_per_frame_ms as f32;
                             animation.current_frame_index += 1;
                             if animation.current_frame_index >= animation.sprites.len() {
                                 animation.current_frame_index = 0;
@@ -0,0 +1,26 @@
+use std::cell::RefMut;
+use crate::components::*;
+
+// Keeps the player centered horizontally without ever showing anything past the edges of the level
+pub fn camera_system(
+    camera: &mut Camera,
+    position_component_vector: &Vec<Option<Position>>,
+    rigid_body_component_vector: &RefMut<Vec<Option<RigidBody>>>,
+    player_index: Option<usize>,
+    level_size: [f32; 2],
+) {
+    if let Some(player_index) = player_index {
+        if let (
+            Some(Some(position)),
+            Some(Some(rigid_body)),
+        ) = (
+            position_component_vector.get(player_index),
+            rigid_body_component_vector.get(player_index),
+        ) {
+            let max_x = (level_size[0] - 1.).max(0.);
+            let max_y = (level_size[1] - 1.).max(0.);
+            camera.x = (position.x + rigid_body.width / 2. - 0.5).max(0.).min(max_x);
+            camera.y = (position.y + rigid_body.height / 2. - 0.5).max(0.).min(max_y);
+        }
+    }
+}
@@ -1,11 +1,13 @@
 use std::cell::{RefCell, RefMut};
 use crate::components::*;
+use crate::spatial_hash::SpatialHash;
 
 pub fn collision_system(
     mut position_component_vector: &mut RefMut<Vec<Option<Position>>>, 
     mut rigid_body_component_vector: &mut RefMut<Vec<Option<RigidBody>>>, 
     mut collision_list_component_vector: &mut RefMut<Vec<Option<CollisionList>>>, 
-    time_passed: u128
+    spatial_hash: &SpatialHash,
+    time_passed: f32
 ) {
     // Clear collision lists from before
     {
@@ -33,11 +35,9 @@ pub fn collision_system(
             // Save collision to collision list of the object - only really do the test if the object has a collision list (wants to react to collisions)
             let mut collision_list = collision_list_component_vector.get_mut(i_a).unwrap();
             if let Some(collision_list) = collision_list {
-                let iter_b = {
-                    let rigid_body_iter_b = rigid_body_component_vector.iter();
-                    let position_iter_b = position_component_vector.iter();
-                    rigid_body_iter_b.enumerate().zip(position_iter_b).filter_map(|((i, rigid_body), position)| Some((rigid_body.as_ref()?, position.as_ref()?, i)))
-                };
+                // Only what shares a cell with it can be touching
+                let iter_b = spatial_hash.query([min_x_a, min_y_a], [max_x_a, max_y_a]).into_iter()
+                    .filter_map(|i| Some((rigid_body_component_vector.get(i)?.as_ref()?, position_component_vector.get(i)?.as_ref()?, i)));
                 for (rigid_body_b, position_b, i_b) in iter_b {
                     if !std::ptr::eq(rigid_body_a, rigid_body_b) {
                         let min_x_b = position_b.x;
@@ -7,4 +7,7 @@ pub mod collision;
 pub mod player_animation;
 pub mod player_pineapple;
 pub mod points_ticking_down;
-pub mod flag_reached;
\ No newline at end of file
+pub mod flag_reached;
+pub mod particles;
+pub mod camera;
+pub mod spin;
\ No newline at end of file
@@ -0,0 +1,63 @@
+use std::cell::RefMut;
+use crate::components::*;
+use crate::random::{random, random_in_range};
+
+pub fn particles_system(
+    particle_emitter_component_vector: &mut RefMut<Vec<Option<ParticleEmitter>>>,
+    position_component_vector: &RefMut<Vec<Option<Position>>>,
+    marked_for_deletion_component_vector: &mut Option<RefMut<Vec<Option<MarkedForDeletion>>>>,
+    time_passed: f32,
+) {
+    let seconds_passed = time_passed / 1000.;
+    let emitter_iter = particle_emitter_component_vector.iter_mut().enumerate();
+    let position_iter = position_component_vector.iter();
+    let iter = emitter_iter.zip(position_iter).filter_map(|((index, emitter), position)| Some((emitter.as_mut()?, position.as_ref()?, index)));
+    for (emitter, position, index) in iter {
+        // Age and move what's alive
+        emitter.particles.retain(|particle| particle.age_ms + time_passed < particle.lifetime_ms as f32);
+        for particle in emitter.particles.iter_mut() {
+            particle.age_ms += time_passed;
+            particle.vel_y += particle.gravity * seconds_passed;
+            particle.x += particle.vel_x * seconds_passed;
+            particle.y += particle.vel_y * seconds_passed;
+        }
+
+        // Spawn new ones
+        let mut to_spawn = emitter.burst;
+        emitter.burst = 0;
+        if emitter.emitting {
+            emitter.spawn_accumulator += emitter.spawn_rate * seconds_passed;
+            to_spawn += emitter.spawn_accumulator as u32;
+            emitter.spawn_accumulator = emitter.spawn_accumulator.fract();
+        }
+        for _ in 0..to_spawn {
+            let particle = Particle {
+                x: position.x + emitter.offset[0] + (random(&mut emitter.rng_state) - 0.5) * emitter.spawn_area[0],
+                y: position.y + emitter.offset[1] + (random(&mut emitter.rng_state) - 0.5) * emitter.spawn_area[1],
+                vel_x: random_in_range(&mut emitter.rng_state, emitter.vel_x),
+                vel_y: random_in_range(&mut emitter.rng_state, emitter.vel_y),
+                gravity: random_in_range(&mut emitter.rng_state, emitter.gravity),
+                age_ms: 0.,
+                lifetime_ms: random_in_range(&mut emitter.rng_state, [emitter.lifetime_ms[0] as f32, emitter.lifetime_ms[1] as f32]) as u32,
+            };
+            emitter.particles.push(particle);
+        }
+
+        // One shot emitters clean up after themselves
+        if emitter.despawn_when_done && !emitter.emitting && emitter.particles.is_empty() {
+            if let Some(marked_for_deletion_component_vector) = marked_for_deletion_component_vector {
+                if let Some(Some(marked_for_deletion)) = marked_for_deletion_component_vector.get_mut(index) {
+                    marked_for_deletion.marked = true;
+                }
+            }
+        }
+    }
+}
+
+// How far along its life the particle is, from 0 to 1
+pub fn particle_progress(particle: &Particle) -> f32 {
+    if particle.lifetime_ms == 0 {
+        return 1.;
+    }
+    (particle.age_ms / particle.lifetime_ms as f32).min(1.)
+}
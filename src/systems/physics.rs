@@ -1,134 +1,341 @@
 use std::cell::{RefCell, RefMut};
 use crate::components::*;
+use crate::spatial_hash::SpatialHash;
 
+// Boxes this close count as touching rather than overlapping, well under a pixel at any resolution
+const CONTACT_EPSILON: f32 = 0.00001;
+// Times a body can hit something and slide on with what's left of its move in one step, enough for a corner
+const MAX_SLIDES: usize = 3;
+
+// A moving body ran into something that blocks movement. The normal points from the surface that got hit
+// back at the body, so [0., -1.] is standing on a floor
+#[derive(Clone, Copy, Debug)]
+pub struct Contact {
+    pub entity: usize,
+    pub normal: [f32; 2],
+}
+
+// Moves everything with a velocity, sweeping each box along its move so it stops exactly where it touches
+// a blocking box, however fast it goes. Whatever is left of the move goes on along the surface it hit.
+// Only boxes the spatial hash has near the move get looked at, and the hash follows the bodies as they move
 pub fn physics_system(
-    mut velocity_component_vector: &mut RefMut<Vec<Option<Velocity>>>, 
-    mut position_component_vector: &mut RefMut<Vec<Option<Position>>>, 
-    mut rigid_body_component_vector: &mut RefMut<Vec<Option<RigidBody>>>, 
-    mut blocks_movement_component_vector: &mut RefMut<Vec<Option<BlocksMovement>>>, 
-    time_passed: u128
-) {
-    // Move multiple times in smaller steps so we get as close to the other rigid_body as possible?
-    let steps: usize = 4;
-
-    for _ in 0..steps {
-        let time_passed: f32 = time_passed as f32 / steps as f32;
-
-        // Collect movement_allowed_stuff
-        let movement_allowed_vector: Vec<AcceptMovement> = {
-            let mut movement_allowed: Vec<AcceptMovement> = vec![AcceptMovement::Neither; position_component_vector.len()];
-
-            let velocity_iter = velocity_component_vector.iter().enumerate();
-            let position_iter = position_component_vector.iter();
-            let rigid_body_iter = rigid_body_component_vector.iter();
-            let zip = velocity_iter.zip(position_iter.zip(rigid_body_iter));
-            let iter = zip.filter_map(|((index, velocity), (position, rigid_body))| Some((velocity.as_ref()?, position.as_ref()?, rigid_body.as_ref()?, index)));
-
-            // Collect info if we're accepting the X or Y change (or both)
-            for (velocity, position, rigid_body, index) in iter {
-                
-                // Try moving on X, check for collisions and record info
-                let accept_x = {
-                    let new_pos = Position {x: position.x + velocity.vel_x * time_passed / 1000., y: position.y};
-                    !collides_with_another_rb(&new_pos, &rigid_body, &position_component_vector, &rigid_body_component_vector, &blocks_movement_component_vector)
-                };
-                
-                // Try moving on Y, check for collisions and record info
-                let accept_y = {
-                    let new_pos = Position {x: position.x, y: position.y + velocity.vel_y * time_passed / 1000.};
-                    !collides_with_another_rb(&new_pos, &rigid_body, &position_component_vector, &rigid_body_component_vector, &blocks_movement_component_vector)
-                };
-
-                let accept_both = {
-                    let new_pos = Position {x: position.x + velocity.vel_x * time_passed / 1000., y: position.y + velocity.vel_y * time_passed / 1000.};
-                    !collides_with_another_rb(&new_pos, &rigid_body, &position_component_vector, &rigid_body_component_vector, &blocks_movement_component_vector)
-                };
-
-                let accept_movement = {
-                    if accept_both {
-                        AcceptMovement::Both
-                    } else if accept_x {
-                        AcceptMovement::OnX
-                    } else if accept_y {
-                        AcceptMovement::OnY
-                    } else {
-                        AcceptMovement::Neither
-                    }
-                };
-                movement_allowed[index] = accept_movement;
-            }
+    mut velocity_component_vector: &mut RefMut<Vec<Option<Velocity>>>,
+    mut position_component_vector: &mut RefMut<Vec<Option<Position>>>,
+    mut rigid_body_component_vector: &mut RefMut<Vec<Option<RigidBody>>>,
+    mut blocks_movement_component_vector: &mut RefMut<Vec<Option<BlocksMovement>>>,
+    spatial_hash: &mut SpatialHash,
+    time_passed: f32
+) -> Vec<Contact> {
+    let mut contacts: Vec<Contact> = Vec::new();
+
+    let movers: Vec<usize> = {
+        let velocity_iter = velocity_component_vector.iter().enumerate();
+        let zip = velocity_iter.zip(position_component_vector.iter().zip(rigid_body_component_vector.iter()));
+        zip.filter(|((_, velocity), (position, rigid_body))| velocity.is_some() && position.is_some() && rigid_body.is_some())
+            .map(|((index, _), _)| index)
+            .collect()
+    };
 
-            movement_allowed
+    for index in movers {
+        let mut remaining = match &velocity_component_vector[index] {
+            Some(velocity) => [velocity.vel_x * time_passed / 1000., velocity.vel_y * time_passed / 1000.],
+            None => continue,
+        };
+        let start = match (&position_component_vector[index], &rigid_body_component_vector[index]) {
+            (Some(position), Some(rigid_body)) => Aabb::new(position, rigid_body),
+            _ => continue,
         };
 
-        // Apply movement based on the allowed stuff
-        {
-            let velocity_iter = velocity_component_vector.iter_mut().enumerate();
-            let position_iter = position_component_vector.iter_mut();
-            let zip = velocity_iter.zip(position_iter);
-            let iter = zip.filter_map(|((index, velocity), position)| Some((velocity.as_mut()?, position.as_mut()?, index)));
-            for (velocity, position, index) in iter {
-                if let Some(movement_allowed) = movement_allowed_vector.get(index) {
-                    match movement_allowed {
-                        AcceptMovement::Both => {
-                            position.x += velocity.vel_x * time_passed / 1000.;
-                            position.y += velocity.vel_y * time_passed / 1000.;
-                        },
-                        AcceptMovement::OnX => {
-                            position.x += velocity.vel_x * time_passed / 1000.;
-                            velocity.vel_y = 0.;
-                        },
-                        AcceptMovement::OnY => {
-                            position.y += velocity.vel_y * time_passed / 1000.;
-                            velocity.vel_x = 0.;
-                        }
-                        AcceptMovement::Neither => {
-                            velocity.vel_x = 0.;
-                            velocity.vel_y = 0.;
-                        }
+        for _ in 0..MAX_SLIDES {
+            if remaining == [0., 0.] {
+                break;
+            }
+            let aabb = match (&position_component_vector[index], &rigid_body_component_vector[index]) {
+                (Some(position), Some(rigid_body)) => Aabb::new(position, rigid_body),
+                _ => break,
+            };
+            // Everything the box could touch on the way
+            let swept_min = [aabb.min[0] + remaining[0].min(0.), aabb.min[1] + remaining[1].min(0.)];
+            let swept_max = [aabb.max[0] + remaining[0].max(0.), aabb.max[1] + remaining[1].max(0.)];
+            let hit = blockers(index, swept_min, swept_max, spatial_hash, &position_component_vector, &rigid_body_component_vector, &blocks_movement_component_vector)
+                .iter()
+                .filter_map(|other_aabb| sweep(&aabb, remaining, other_aabb))
+                .min_by(|(time_a, _), (time_b, _)| time_a.total_cmp(time_b));
+
+            let (velocity, position) = match (&mut velocity_component_vector[index], &mut position_component_vector[index]) {
+                (Some(velocity), Some(position)) => (velocity, position),
+                _ => break,
+            };
+            match hit {
+                None => {
+                    position.x += remaining[0];
+                    position.y += remaining[1];
+                    break;
+                },
+                Some((time, normal)) => {
+                    position.x += remaining[0] * time;
+                    position.y += remaining[1] * time;
+                    contacts.push(Contact {entity: index, normal});
+
+                    // Slide on with the rest of the move, minus the part going into the surface
+                    remaining = [remaining[0] * (1. - time), remaining[1] * (1. - time)];
+                    if normal[0] != 0. {
+                        remaining[0] = 0.;
+                        velocity.vel_x = 0.;
+                    } else {
+                        remaining[1] = 0.;
+                        velocity.vel_y = 0.;
                     }
-                }
+                },
+            }
+        }
+
+        if let (Some(position), Some(rigid_body)) = (&position_component_vector[index], &rigid_body_component_vector[index]) {
+            let end = Aabb::new(position, rigid_body);
+            if end.min != start.min {
+                spatial_hash.remove(index, start.min, start.max);
+                spatial_hash.insert(index, end.min, end.max);
             }
         }
     }
+
+    contacts
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Aabb {
+    min: [f32; 2],
+    max: [f32; 2],
 }
 
-#[derive(Clone, Debug)]
-enum AcceptMovement {
-    OnX,
-    OnY,
-    Both,
-    Neither
+impl Aabb {
+    fn new(position: &Position, rigid_body: &RigidBody) -> Self {
+        Self {min: [position.x, position.y], max: [position.x + rigid_body.width, position.y + rigid_body.height]}
+    }
 }
 
-fn collides_with_another_rb (
-    position_a: &Position, 
-    rigid_body_a: &RigidBody, 
-    all_positions: &Vec<Option<Position>>, 
+// Boxes that block movement somewhere around min to max, other than the one at index
+fn blockers(
+    index: usize,
+    min: [f32; 2],
+    max: [f32; 2],
+    spatial_hash: &SpatialHash,
+    all_positions: &Vec<Option<Position>>,
     all_rigid_bodies: &Vec<Option<RigidBody>>,
     all_blocks_movement: &Vec<Option<BlocksMovement>>,
-) -> bool {
-    // Simple AABB collision detection
-    let min_x_a = position_a.x;
-    let min_y_a = position_a.y;
-    let max_x_a = position_a.x + rigid_body_a.width;
-    let max_y_a = position_a.y + rigid_body_a.height;
-    let iter_b = {
-        let rigid_body_iter_b = all_rigid_bodies.iter();
-        let position_iter_b = all_positions.iter();
-        let blocks_movement_iter_b = all_blocks_movement.iter();
-        rigid_body_iter_b.enumerate().zip(position_iter_b.zip(blocks_movement_iter_b)).filter_map(|((i, rigid_body), (position, blocks_movement))| Some((rigid_body.as_ref()?, position.as_ref()?, blocks_movement.as_ref()?, i)))
-    };
-    for (rigid_body_b, position_b, blocks_movement_b, i_b) in iter_b {
-        if !std::ptr::eq(rigid_body_a, rigid_body_b) && blocks_movement_b.blocks {
-            let min_x_b = position_b.x;
-            let min_y_b = position_b.y;
-            let max_x_b = position_b.x + rigid_body_b.width;
-            let max_y_b = position_b.y + rigid_body_b.height;
-            if min_x_a < max_x_b && max_x_a > min_x_b && min_y_a < max_y_b && max_y_a > min_y_b {
-                return true
+) -> Vec<Aabb> {
+    spatial_hash.query(min, max).into_iter()
+        .filter(|other| *other != index)
+        .filter_map(|other| {
+            if !all_blocks_movement.get(other)?.as_ref()?.blocks {
+                return None;
             }
+            Some(Aabb::new(all_positions.get(other)?.as_ref()?, all_rigid_bodies.get(other)?.as_ref()?))
+        })
+        .collect()
+}
+
+// When box a moving by delta first touches box b, as a fraction of the move, and the normal of the side it hits.
+// None if it never does, or if the two already overlap and there's no telling which way is out
+fn sweep(a: &Aabb, delta: [f32; 2], b: &Aabb) -> Option<(f32, [f32; 2])> {
+    let (entry_x, exit_x) = axis_overlap(a.min[0], a.max[0], b.min[0], b.max[0], delta[0])?;
+    let (entry_y, exit_y) = axis_overlap(a.min[1], a.max[1], b.min[1], b.max[1], delta[1])?;
+    let entry = entry_x.max(entry_y);
+    let exit = exit_x.min(exit_y);
+    if entry == f32::NEG_INFINITY || entry >= exit || entry > 1. {
+        return None;
+    }
+    // Hitting a corner dead on counts as landing on top of it, so running over the seam between two tiles doesn't snag
+    let normal = if entry_x > entry_y { [-delta[0].signum(), 0.] } else { [0., -delta[1].signum()] };
+    Some((entry, normal))
+}
+
+// The fractions of a move along one axis where a's span starts and stops overlapping b's. Spans that overlap
+// from the start begin at negative infinity, ones that only touch don't overlap at all
+fn axis_overlap(a_min: f32, a_max: f32, b_min: f32, b_max: f32, delta: f32) -> Option<(f32, f32)> {
+    if delta == 0. {
+        let overlapping = a_min < b_max - CONTACT_EPSILON && a_max > b_min + CONTACT_EPSILON;
+        return if overlapping { Some((f32::NEG_INFINITY, f32::INFINITY)) } else { None };
+    }
+    // How far until the spans touch, and until a has gone all the way past b
+    let (gap, past) = if delta > 0. { (b_min - a_max, b_max - a_min) } else { (a_min - b_max, a_max - b_min) };
+    if past <= CONTACT_EPSILON {
+        return None;
+    }
+    let distance = delta.abs();
+    let entry = if gap < -CONTACT_EPSILON { f32::NEG_INFINITY } else { gap.max(0.) / distance };
+    Some((entry, past / distance))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A world of boxes, the first one the body that moves, the rest blocking tiles given as min and max
+    struct World {
+        velocities: RefCell<Vec<Option<Velocity>>>,
+        positions: RefCell<Vec<Option<Position>>>,
+        rigid_bodies: RefCell<Vec<Option<RigidBody>>>,
+        blocks_movement: RefCell<Vec<Option<BlocksMovement>>>,
+        spatial_hash: SpatialHash,
+    }
+
+    impl World {
+        fn new(body: ([f32; 2], [f32; 2]), velocity: [f32; 2], tiles: &[([f32; 2], [f32; 2])]) -> Self {
+            let boxes = std::iter::once(&body).chain(tiles.iter());
+            let positions: Vec<Option<Position>> = boxes.clone().map(|(min, _)| Some(Position {x: min[0], y: min[1]})).collect();
+            let rigid_bodies: Vec<Option<RigidBody>> = boxes.map(|(min, max)| Some(RigidBody {width: max[0] - min[0], height: max[1] - min[1]})).collect();
+            let mut velocities: Vec<Option<Velocity>> = (0..positions.len()).map(|_| None).collect();
+            velocities[0] = Some(Velocity {vel_x: velocity[0], vel_y: velocity[1]});
+            let blocks_movement = (0..positions.len()).map(|index| Some(BlocksMovement {blocks: index != 0})).collect();
+            let mut spatial_hash = SpatialHash::default();
+            spatial_hash.rebuild(&positions, &rigid_bodies);
+            Self {
+                velocities: RefCell::new(velocities),
+                positions: RefCell::new(positions),
+                rigid_bodies: RefCell::new(rigid_bodies),
+                blocks_movement: RefCell::new(blocks_movement),
+                spatial_hash,
+            }
+        }
+
+        // One second, so the velocity is exactly the move
+        fn step(&mut self) -> Vec<Contact> {
+            physics_system(
+                &mut self.velocities.borrow_mut(),
+                &mut self.positions.borrow_mut(),
+                &mut self.rigid_bodies.borrow_mut(),
+                &mut self.blocks_movement.borrow_mut(),
+                &mut self.spatial_hash,
+                1000.,
+            )
+        }
+
+        fn body_position(&self) -> [f32; 2] {
+            let position = self.positions.borrow()[0].unwrap();
+            [position.x, position.y]
         }
+
+        fn body_velocity(&self) -> [f32; 2] {
+            let velocities = self.velocities.borrow();
+            let velocity = velocities[0].as_ref().unwrap();
+            [velocity.vel_x, velocity.vel_y]
+        }
+    }
+
+    fn assert_close(actual: [f32; 2], expected: [f32; 2]) {
+        let close = (actual[0] - expected[0]).abs() < 1e-6 && (actual[1] - expected[1]).abs() < 1e-6;
+        assert!(close, "expected {:?}, got {:?}", expected, actual);
+    }
+
+    #[test]
+    fn runs_over_the_seam_between_two_tiles_without_snagging() {
+        let left_tile = ([0., 0.5], [0.125, 0.625]);
+        let right_tile = ([0.125, 0.5], [0.25, 0.625]);
+        // Standing on the left tile with its right side exactly on the seam, pushing into the floor as it goes
+        let mut world = World::new(([0.0625, 0.4375], [0.125, 0.5]), [0.0625, 0.03125], &[left_tile, right_tile]);
+
+        let contacts = world.step();
+
+        assert_close(world.body_position(), [0.125, 0.4375]);
+        assert_close(world.body_velocity(), [0.0625, 0.]);
+        assert!(!contacts.is_empty());
+        assert!(contacts.iter().all(|contact| contact.normal == [0., -1.]), "snagged on the seam: {:?}", contacts);
     }
-    false
-}
\ No newline at end of file
+
+    #[test]
+    fn lands_across_the_seam_between_two_tiles() {
+        let left_tile = ([0., 0.5], [0.125, 0.625]);
+        let right_tile = ([0.125, 0.5], [0.25, 0.625]);
+        // Falling straight down onto both tiles at once
+        let mut world = World::new(([0.09375, 0.375], [0.15625, 0.4375]), [0., 0.125], &[left_tile, right_tile]);
+
+        let contacts = world.step();
+
+        assert_close(world.body_position(), [0.09375, 0.4375]);
+        assert_close(world.body_velocity(), [0., 0.]);
+        assert!(contacts.iter().all(|contact| contact.normal == [0., -1.]));
+    }
+
+    #[test]
+    fn does_not_tunnel_through_a_thin_tile() {
+        let thin_tile = ([0., 0.5], [0.25, 0.5078125]);
+        // Moves four times the distance to the tile in one step, way past it
+        let mut world = World::new(([0.0625, 0.3125], [0.125, 0.375]), [0., 1.], &[thin_tile]);
+
+        let contacts = world.step();
+
+        assert_close(world.body_position(), [0.0625, 0.4375]);
+        assert_close(world.body_velocity(), [0., 0.]);
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts[0].normal, [0., -1.]);
+    }
+
+    #[test]
+    fn slides_along_a_wall_when_moving_diagonally() {
+        let wall = ([0.25, 0.], [0.375, 1.]);
+        let mut world = World::new(([0.125, 0.25], [0.1875, 0.3125]), [0.125, 0.125], &[wall]);
+
+        let contacts = world.step();
+
+        // Stops at the wall halfway through and keeps going down for the whole move
+        assert_close(world.body_position(), [0.1875, 0.375]);
+        assert_close(world.body_velocity(), [0., 0.125]);
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts[0].normal, [-1., 0.]);
+    }
+
+    #[test]
+    fn hitting_a_corner_dead_on_lands_on_top() {
+        let tile = ([0.125, 0.5], [0.25, 0.625]);
+        // Reaches the tile's top left corner on both axes at the same time
+        let mut world = World::new(([0., 0.375], [0.0625, 0.4375]), [0.125, 0.125], &[tile]);
+
+        let contacts = world.step();
+
+        assert_close(world.body_position(), [0.125, 0.4375]);
+        assert_close(world.body_velocity(), [0.125, 0.]);
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts[0].normal, [0., -1.]);
+    }
+
+    #[test]
+    fn resting_on_the_floor_hits_it_right_away() {
+        let floor = ([0., 0.5], [1., 0.625]);
+        let mut world = World::new(([0.25, 0.4375], [0.3125, 0.5]), [0., 0.0625], &[floor]);
+
+        let contacts = world.step();
+
+        assert_close(world.body_position(), [0.25, 0.4375]);
+        assert_close(world.body_velocity(), [0., 0.]);
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts[0].normal, [0., -1.]);
+    }
+
+    #[test]
+    fn resting_slightly_inside_the_floor_still_counts_as_touching() {
+        let floor = ([0., 0.5], [1., 0.625]);
+        // Sunk in by less than CONTACT_EPSILON, which must not read as overlapping and let it fall through
+        let sunk = CONTACT_EPSILON / 2.;
+        let mut world = World::new(([0.25, 0.4375 + sunk], [0.3125, 0.5 + sunk]), [0., 0.0625], &[floor]);
+
+        let contacts = world.step();
+
+        assert_close(world.body_position(), [0.25, 0.4375 + sunk]);
+        assert_close(world.body_velocity(), [0., 0.]);
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts[0].normal, [0., -1.]);
+    }
+
+    #[test]
+    fn moving_sideways_while_resting_on_the_floor_is_not_blocked() {
+        let floor = ([0., 0.5], [1., 0.625]);
+        let mut world = World::new(([0.25, 0.4375], [0.3125, 0.5]), [0.125, 0.], &[floor]);
+
+        let contacts = world.step();
+
+        assert_close(world.body_position(), [0.375, 0.4375]);
+        assert_close(world.body_velocity(), [0.125, 0.]);
+        assert!(contacts.is_empty());
+    }
+}
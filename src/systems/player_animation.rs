@@ -56,14 +56,14 @@ fn disable_current_animation (animation_map: &mut AnimationMap) {
     if let Some(mut animation) = animation_map.map.get_mut(animation_map.current_animation_name) {
         animation.running = false;
         animation.current_frame_index = 0;
-        animation.time_since_last_frame = 0;
+        animation.time_since_last_frame = 0.;
     }
 }
 fn enable_animation (animation_map: &mut AnimationMap, new_animation_name: &str) {
     if let Some(mut animation) = animation_map.map.get_mut(new_animation_name) {
         animation.running = true;
         animation.current_frame_index = 0;
-        animation.time_since_last_frame = 0;
+        animation.time_since_last_frame = 0.;
         animation_map.current_animation_name = animation.animation_name;
     }
 }
@@ -0,0 +1,10 @@
+use std::cell::RefMut;
+use crate::components::*;
+
+pub fn spin_system(spin_component_vector: &RefMut<Vec<Option<Spin>>>, sprite_component_vector: &mut RefMut<Vec<Option<Sprite>>>, time_passed: f32) {
+    let zip = spin_component_vector.iter().zip(sprite_component_vector.iter_mut());
+    let iter = zip.filter_map(|(spin, sprite)| Some((spin.as_ref()?, sprite.as_mut()?)));
+    for (spin, sprite) in iter {
+        sprite.rotation = (sprite.rotation + spin.speed * time_passed / 1000.) % std::f32::consts::TAU;
+    }
+}
\ No newline at end of file
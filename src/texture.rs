@@ -12,15 +12,16 @@ pub struct Texture {
     pub vertex_buffer: Option<wgpu::Buffer>,
     pub width: u32,
     pub height: u32,
+    pub opaque: bool, // No translucent pixels, can be drawn without blending
 }
 
 impl Texture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
-    pub fn create_depth_texture(device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor, label: &str) -> Self {
+    pub fn create_depth_texture(device: &wgpu::Device, width: u32, height: u32, sample_count: u32, label: &str) -> Self {
         let size = wgpu::Extent3d {
-            width: sc_desc.width,
-            height: sc_desc.height,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
 
@@ -28,7 +29,7 @@ impl Texture {
             label: Some(label),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
             usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
@@ -51,21 +52,22 @@ impl Texture {
             }
         );
 
-        Self { texture, view, sampler, bind_group: None, vertex_buffer: None, width: 0, height: 0 }
+        Self { texture, view, sampler, bind_group: None, vertex_buffer: None, width, height, opaque: true }
     }
 
     pub fn from_bytes(device: &wgpu::Device, queue: &wgpu::Queue, bytes: &[u8], label: &str, layout: &wgpu::BindGroupLayout) -> Result<Self> {
         let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label), layout)
+        Self::from_image(device, queue, &img, Some(label), layout, &TextureOptions::default())
     }
-    pub fn from_image(device: &wgpu::Device, queue: &wgpu::Queue, img: &image::DynamicImage, label: Option<&str>, layout: &wgpu::BindGroupLayout) -> Result<Self> {
+    pub fn from_image(device: &wgpu::Device, queue: &wgpu::Queue, img: &image::DynamicImage, label: Option<&str>, layout: &wgpu::BindGroupLayout, options: &TextureOptions) -> Result<Self> {
         let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
 
-        let text_size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth_or_array_layers: 1
+        // Every level halves the size down to 1x1
+        let mip_level_count = if options.mipmaps {
+            32 - dimensions.0.max(dimensions.1).leading_zeros()
+        } else {
+            1
         };
         let texture: wgpu::Texture = device.create_texture(
             &wgpu::TextureDescriptor {
@@ -74,35 +76,49 @@ impl Texture {
                     height: dimensions.1,
                     depth_or_array_layers: 1,
                 },
-                mip_level_count: 1,
+                mip_level_count,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                format: if options.srgb { wgpu::TextureFormat::Rgba8UnormSrgb } else { wgpu::TextureFormat::Rgba8Unorm },
                 usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
-                label: Some("diffuse_texture")
+                label,
             }
         );
-        queue.write_texture(wgpu::ImageCopyTexture {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            }, 
-            &rgba, 
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(NonZeroU32::new(4 * dimensions.0).unwrap()),
-                rows_per_image: Some(NonZeroU32::new(dimensions.1).unwrap())
-            },
-            text_size
-        );
+        // Mips get downscaled on the cpu, textures are small and only loaded once
+        for mip_level in 0..mip_level_count {
+            let level = if mip_level == 0 {
+                rgba.clone()
+            } else {
+                let width = (dimensions.0 >> mip_level).max(1);
+                let height = (dimensions.1 >> mip_level).max(1);
+                image::imageops::resize(&rgba, width, height, image::imageops::FilterType::Triangle)
+            };
+            queue.write_texture(wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                }, 
+                &level, 
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(NonZeroU32::new(4 * level.width()).unwrap()),
+                    rows_per_image: Some(NonZeroU32::new(level.height()).unwrap())
+                },
+                wgpu::Extent3d {
+                    width: level.width(),
+                    height: level.height(),
+                    depth_or_array_layers: 1
+                }
+            );
+        }
         let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         let texture_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            address_mode_u: options.address_mode,
+            address_mode_v: options.address_mode,
+            address_mode_w: options.address_mode,
+            mag_filter: options.filter,
+            min_filter: options.filter,
+            mipmap_filter: options.filter,
             ..Default::default()
         });
 
@@ -125,13 +141,13 @@ impl Texture {
             &wgpu::util::BufferInitDescriptor {
                 label: None,
                 contents: bytemuck::cast_slice(&[
-                    Vertex{position: [-1.0, 1.0, 0.0], tex_coords: [0.0, 0.0]},
-                    Vertex{position: [-1.0, -1.0, 0.0], tex_coords: [0.0, 1.0]},
-                    Vertex{position: [1.0, -1.0, 0.0], tex_coords: [1.0, 1.0]},
+                    Vertex{position: [-1.0, 1.0, 0.0], tex_coords: [0.0, 0.0], color: [1.0, 1.0, 1.0, 1.0], params: [0.0; 4]},
+                    Vertex{position: [-1.0, -1.0, 0.0], tex_coords: [0.0, 1.0], color: [1.0, 1.0, 1.0, 1.0], params: [0.0; 4]},
+                    Vertex{position: [1.0, -1.0, 0.0], tex_coords: [1.0, 1.0], color: [1.0, 1.0, 1.0, 1.0], params: [0.0; 4]},
 
-                    Vertex{position: [-1.0, 1.0, 0.0], tex_coords: [0.0, 0.0]},
-                    Vertex{position: [1.0, -1.0, 0.0], tex_coords: [1.0, 1.0]},
-                    Vertex{position: [1.0, 1.0, 0.0], tex_coords: [1.0, 0.0]},
+                    Vertex{position: [-1.0, 1.0, 0.0], tex_coords: [0.0, 0.0], color: [1.0, 1.0, 1.0, 1.0], params: [0.0; 4]},
+                    Vertex{position: [1.0, -1.0, 0.0], tex_coords: [1.0, 1.0], color: [1.0, 1.0, 1.0, 1.0], params: [0.0; 4]},
+                    Vertex{position: [1.0, 1.0, 0.0], tex_coords: [1.0, 0.0], color: [1.0, 1.0, 1.0, 1.0], params: [0.0; 4]},
 
                 ]),
                 usage: wgpu::BufferUsage::VERTEX,
@@ -146,6 +162,7 @@ impl Texture {
             vertex_buffer: Some(vertex_buffer), 
             width: dimensions.0,
             height: dimensions.1, 
+            opaque: rgba.pixels().all(|pixel| pixel[3] == 255),
         })
     }
 
@@ -153,23 +170,61 @@ impl Texture {
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         path: P,
-        layout: &wgpu::BindGroupLayout
+        layout: &wgpu::BindGroupLayout,
+        options: &TextureOptions,
     ) -> Result<Self> {
         let path_copy = path.as_ref().to_path_buf();
         let label = path_copy.to_str();
 
         let img = image::open(path)?;
-        Self::from_image(device, queue, &img, label, layout)
+        Self::from_image(device, queue, &img, label, layout, options)
     }
 
     // pub fn draw(&mut self, )
 }
 
+// How a texture gets sampled, picked when it's registered
+#[derive(Clone, Copy, Debug)]
+pub struct TextureOptions {
+    pub filter: wgpu::FilterMode, // Nearest keeps pixel art sharp when scaled up
+    pub address_mode: wgpu::AddressMode, // Repeat or MirrorRepeat for tiling
+    pub mipmaps: bool, // For textures that get drawn a lot smaller than they are
+    pub srgb: bool, // Colors are sRGB, data like normal maps isn't
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            filter: wgpu::FilterMode::Nearest,
+            address_mode: wgpu::AddressMode::ClampToEdge,
+            mipmaps: false,
+            srgb: true,
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct Vertex {
     pub position: [f32; 3],
-    pub tex_coords: [f32; 2]
+    pub tex_coords: [f32; 2],
+    pub color: [f32; 4],
+    pub params: [f32; 4], // Per sprite material parameters, ignored by the default shader
 }
 unsafe impl bytemuck::Pod for Vertex{}
-unsafe impl bytemuck::Zeroable for Vertex{}
\ No newline at end of file
+unsafe impl bytemuck::Zeroable for Vertex{}
+
+// One sprite of the instanced sprite pass, the vertex shader stretches the unit quad over it.
+// Same layout as the per instance inputs of shader.vert
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SpriteInstance {
+    pub origin: [f32; 3], // Clip space top left corner plus depth
+    pub axis_x: [f32; 2], // Top left to top right corner, rotation and scale included
+    pub axis_y: [f32; 2], // Top left to bottom left corner
+    pub tex_rect: [f32; 4],
+    pub color: [f32; 4],
+    pub params: [f32; 4], // Per sprite material parameters, ignored by the default shader
+}
+unsafe impl bytemuck::Pod for SpriteInstance{}
+unsafe impl bytemuck::Zeroable for SpriteInstance{}
\ No newline at end of file
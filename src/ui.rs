@@ -0,0 +1,386 @@
+use crate::renderer::*;
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
+
+// What the UI uses when something can be focused, gameplay never sees these then
+const NAVIGATION_KEYS: [VirtualKeyCode; 7] = [
+    VirtualKeyCode::Up,
+    VirtualKeyCode::Down,
+    VirtualKeyCode::Left,
+    VirtualKeyCode::Right,
+    VirtualKeyCode::Tab,
+    VirtualKeyCode::Return,
+    VirtualKeyCode::Space,
+];
+// Above sprites on the UI layer that aren't part of a widget tree
+const BASE_Z: u32 = 1;
+
+// Drawn behind a widget, stretched over its whole area including the padding
+#[derive(Clone, Copy)]
+pub struct Background {
+    pub texture_id: usize,
+    pub nine_slice: Option<NineSlice>,
+    pub tint: [f32; 4],
+}
+
+// Sizes, offsets and spacing are normalized screen units like everywhere else
+pub enum WidgetKind {
+    Row { children: Vec<Widget>, spacing: f32, align: VerticalAlign },
+    Column { children: Vec<Widget>, spacing: f32, align: HorizontalAlign },
+    // Takes up all the space it's given and puts the child at the anchor, [0.5, 0.5] centers it
+    Anchor { anchor: [f32; 2], offset: [f32; 2], child: Box<Widget> },
+    Label { spans: Vec<TextSpan>, outline: Option<TextOutline>, shadow: Option<TextShadow> },
+    Image { texture_id: usize, size: [f32; 2], tint: [f32; 4] },
+    // Focusable, Return or Space sends UiEvent::Pressed with the widget's id
+    Button { label: Vec<TextSpan>, focus_tint: [f32; 4] },
+    // Texture is usually Renderer::white_texture, tinted with the colors
+    ProgressBar { value: f32, size: [f32; 2], texture_id: usize, fill_color: [f32; 4], empty_color: [f32; 4] },
+}
+
+pub struct Widget {
+    pub id: String, // For finding it again and telling buttons apart, can stay empty otherwise
+    pub kind: WidgetKind,
+    pub padding: [f32; 2],
+    pub min_size: [f32; 2],
+    pub background: Option<Background>,
+    pub visible: bool,
+    pub modal: bool, // Swallows all input while visible, for menus that pause the game
+    pub layout: WidgetLayout, // Filled in by Ui::layout
+}
+
+// Where a widget ended up during the last layout
+#[derive(Clone, Copy, Default)]
+pub struct WidgetLayout {
+    size: [f32; 2],
+    position: [f32; 2],
+}
+
+impl Widget {
+    pub fn new(kind: WidgetKind) -> Self {
+        Self {
+            id: String::new(),
+            kind,
+            padding: [0., 0.],
+            min_size: [0., 0.],
+            background: None,
+            visible: true,
+            modal: false,
+            layout: WidgetLayout::default(),
+        }
+    }
+
+    fn children(&self) -> Vec<&Widget> {
+        match &self.kind {
+            WidgetKind::Row { children, .. } | WidgetKind::Column { children, .. } => children.iter().collect(),
+            WidgetKind::Anchor { child, .. } => vec![child.as_ref()],
+            _ => Vec::new(),
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Widget> {
+        match &mut self.kind {
+            WidgetKind::Row { children, .. } | WidgetKind::Column { children, .. } => children.iter_mut().collect(),
+            WidgetKind::Anchor { child, .. } => vec![child.as_mut()],
+            _ => Vec::new(),
+        }
+    }
+
+    fn find_mut(&mut self, id: &str) -> Option<&mut Widget> {
+        if self.id == id {
+            return Some(self);
+        }
+        self.children_mut().into_iter().find_map(|child| child.find_mut(id))
+    }
+
+    // Depth first, which is also the order focus moves in
+    fn collect_focusable(&self, focusable: &mut Vec<String>) {
+        if !self.visible {
+            return;
+        }
+        if let WidgetKind::Button { .. } = self.kind {
+            focusable.push(self.id.clone());
+        }
+        for child in self.children() {
+            child.collect_focusable(focusable);
+        }
+    }
+
+    fn has_visible_modal(&self) -> bool {
+        self.visible && (self.modal || self.children().iter().any(|child| child.has_visible_modal()))
+    }
+
+    // Bottom up: how much space the widget wants, padding included
+    fn measure(&mut self, renderer: &Renderer) -> [f32; 2] {
+        let content = match &mut self.kind {
+            WidgetKind::Row { children, spacing, .. } => {
+                let sizes: Vec<[f32; 2]> = children.iter_mut().filter(|child| child.visible).map(|child| child.measure(renderer)).collect();
+                let gaps = sizes.len().saturating_sub(1) as f32 * *spacing;
+                [sizes.iter().map(|size| size[0]).sum::<f32>() + gaps, sizes.iter().map(|size| size[1]).fold(0., f32::max)]
+            },
+            WidgetKind::Column { children, spacing, .. } => {
+                let sizes: Vec<[f32; 2]> = children.iter_mut().filter(|child| child.visible).map(|child| child.measure(renderer)).collect();
+                let gaps = sizes.len().saturating_sub(1) as f32 * *spacing;
+                [sizes.iter().map(|size| size[0]).fold(0., f32::max), sizes.iter().map(|size| size[1]).sum::<f32>() + gaps]
+            },
+            WidgetKind::Anchor { child, .. } => child.measure(renderer),
+            WidgetKind::Label { spans, .. } | WidgetKind::Button { label: spans, .. } => {
+                renderer.measure_text(&RenderableText { spans: spans.clone(), ..Default::default() })
+            },
+            WidgetKind::Image { size, .. } | WidgetKind::ProgressBar { size, .. } => *size,
+        };
+        self.layout.size = [
+            (content[0] + self.padding[0] * 2.).max(self.min_size[0]),
+            (content[1] + self.padding[1] * 2.).max(self.min_size[1]),
+        ];
+        self.layout.size
+    }
+
+    // Top down: where everything goes, given the space the parent has for it
+    fn arrange(&mut self, position: [f32; 2], available: [f32; 2]) {
+        self.layout.position = position;
+        let padding = self.padding;
+        let inner_position = [position[0] + padding[0], position[1] + padding[1]];
+        let inner_size = [self.layout.size[0] - padding[0] * 2., self.layout.size[1] - padding[1] * 2.];
+        match &mut self.kind {
+            WidgetKind::Row { children, spacing, align } => {
+                let mut x = inner_position[0];
+                for child in children.iter_mut().filter(|child| child.visible) {
+                    let free = inner_size[1] - child.layout.size[1];
+                    let y = inner_position[1] + match align {
+                        VerticalAlign::Top => 0.,
+                        VerticalAlign::Center => free / 2.,
+                        VerticalAlign::Bottom => free,
+                    };
+                    let child_size = child.layout.size;
+                    child.arrange([x, y], child_size);
+                    x += child_size[0] + *spacing;
+                }
+            },
+            WidgetKind::Column { children, spacing, align } => {
+                let mut y = inner_position[1];
+                for child in children.iter_mut().filter(|child| child.visible) {
+                    let free = inner_size[0] - child.layout.size[0];
+                    let x = inner_position[0] + match align {
+                        HorizontalAlign::Left => 0.,
+                        HorizontalAlign::Center => free / 2.,
+                        HorizontalAlign::Right => free,
+                    };
+                    let child_size = child.layout.size;
+                    child.arrange([x, y], child_size);
+                    y += child_size[1] + *spacing;
+                }
+            },
+            WidgetKind::Anchor { anchor, offset, child } => {
+                self.layout.size = available;
+                let child_size = child.layout.size;
+                let x = position[0] + (available[0] - child_size[0]) * anchor[0] + offset[0];
+                let y = position[1] + (available[1] - child_size[1]) * anchor[1] + offset[1];
+                child.arrange([x, y], child_size);
+            },
+            _ => {},
+        }
+    }
+
+    fn push_renderables(&self, focus: Option<&str>, z: u32, renderables: &mut Vec<Renderable>, texts: &mut Vec<RenderableText>) {
+        if !self.visible {
+            return;
+        }
+        let focused = focus == Some(self.id.as_str()) && !self.id.is_empty();
+        let p1 = self.layout.position;
+        let p2 = [p1[0] + self.layout.size[0], p1[1] + self.layout.size[1]];
+        let inner_p1 = [p1[0] + self.padding[0], p1[1] + self.padding[1]];
+        let inner_p2 = [p2[0] - self.padding[0], p2[1] - self.padding[1]];
+
+        if let Some(background) = &self.background {
+            let tint = match (&self.kind, focused) {
+                (WidgetKind::Button { focus_tint, .. }, true) => *focus_tint,
+                _ => background.tint,
+            };
+            renderables.push(ui_quad(background.texture_id, p1, p2, tint, z, background.nine_slice));
+        }
+
+        match &self.kind {
+            WidgetKind::Label { spans, outline, shadow } => {
+                texts.push(RenderableText {
+                    spans: spans.clone(),
+                    x: inner_p1[0],
+                    y: inner_p1[1],
+                    outline: outline.clone(),
+                    shadow: shadow.clone(),
+                    ..Default::default()
+                });
+            },
+            WidgetKind::Button { label, focus_tint } => {
+                // Without a background the focus shows on the text instead
+                let mut spans = label.clone();
+                if focused && self.background.is_none() {
+                    for span in spans.iter_mut() {
+                        span.color = [span.color[0] * focus_tint[0], span.color[1] * focus_tint[1], span.color[2] * focus_tint[2], span.color[3] * focus_tint[3]];
+                    }
+                }
+                texts.push(RenderableText {
+                    spans,
+                    x: (inner_p1[0] + inner_p2[0]) / 2.,
+                    y: (inner_p1[1] + inner_p2[1]) / 2.,
+                    h_align: HorizontalAlign::Center,
+                    v_align: VerticalAlign::Center,
+                    ..Default::default()
+                });
+            },
+            WidgetKind::Image { texture_id, tint, .. } => {
+                renderables.push(ui_quad(*texture_id, inner_p1, inner_p2, *tint, z + 1, None));
+            },
+            WidgetKind::ProgressBar { value, texture_id, fill_color, empty_color, .. } => {
+                let fill_x = inner_p1[0] + (inner_p2[0] - inner_p1[0]) * value.max(0.).min(1.);
+                renderables.push(ui_quad(*texture_id, inner_p1, inner_p2, *empty_color, z + 1, None));
+                if fill_x > inner_p1[0] {
+                    renderables.push(ui_quad(*texture_id, inner_p1, [fill_x, inner_p2[1]], *fill_color, z + 2, None));
+                }
+            },
+            _ => {},
+        }
+
+        // Children draw over their parent's background
+        for child in self.children() {
+            child.push_renderables(focus, z + 3, renderables, texts);
+        }
+    }
+}
+
+fn ui_quad(texture_id: usize, p1: [f32; 2], p2: [f32; 2], tint: [f32; 4], z: u32, nine_slice: Option<NineSlice>) -> Renderable {
+    Renderable {
+        texture_id,
+        layer: RenderLayer::Ui,
+        z,
+        p1,
+        p2,
+        tint,
+        lit: false,
+        nine_slice,
+        ..Default::default()
+    }
+}
+
+pub enum UiEvent {
+    Pressed(String), // Id of the button
+}
+
+// Widget trees declared by each scene, kept around between frames. Every tree in widgets gets laid out
+// over the whole screen, so they're usually anchors
+#[derive(Default)]
+pub struct Ui {
+    widgets: Vec<Widget>,
+    focus: Option<String>,
+    events: Vec<UiEvent>,
+}
+
+impl Ui {
+    pub fn new(widgets: Vec<Widget>) -> Self {
+        Self { widgets, focus: None, events: Vec::new() }
+    }
+
+    pub fn find_mut(&mut self, id: &str) -> Option<&mut Widget> {
+        self.widgets.iter_mut().find_map(|widget| widget.find_mut(id))
+    }
+
+    pub fn set_visible(&mut self, id: &str, visible: bool) {
+        if let Some(widget) = self.find_mut(id) {
+            widget.visible = visible;
+        }
+    }
+
+    // Replaces the text of one span of a label or button
+    pub fn set_text(&mut self, id: &str, span_index: usize, text: &str) {
+        if let Some(widget) = self.find_mut(id) {
+            if let WidgetKind::Label { spans, .. } | WidgetKind::Button { label: spans, .. } = &mut widget.kind {
+                if let Some(span) = spans.get_mut(span_index) {
+                    span.text = text.to_owned();
+                }
+            }
+        }
+    }
+
+    pub fn set_progress(&mut self, id: &str, new_value: f32) {
+        if let Some(widget) = self.find_mut(id) {
+            if let WidgetKind::ProgressBar { value, .. } = &mut widget.kind {
+                *value = new_value;
+            }
+        }
+    }
+
+    // Moves focus and presses buttons, returns the input that's left for gameplay
+    pub fn handle_input(&mut self, inputs: &[KeyboardInput]) -> Vec<KeyboardInput> {
+        let focusable = self.focusable();
+        let modal = self.widgets.iter().any(|widget| widget.has_visible_modal());
+        if focusable.is_empty() {
+            return if modal { Vec::new() } else { inputs.to_vec() };
+        }
+
+        let mut remaining = Vec::new();
+        for input in inputs {
+            let key = match input.virtual_keycode {
+                Some(key) if NAVIGATION_KEYS.contains(&key) => key,
+                _ => {
+                    if !modal {
+                        remaining.push(*input);
+                    }
+                    continue;
+                },
+            };
+            if input.state != ElementState::Pressed {
+                continue;
+            }
+
+            let current = self.focus.as_ref().and_then(|focus| focusable.iter().position(|id| id == focus));
+            match (key, current) {
+                (VirtualKeyCode::Return, Some(current)) | (VirtualKeyCode::Space, Some(current)) => {
+                    self.events.push(UiEvent::Pressed(focusable[current].clone()));
+                },
+                (VirtualKeyCode::Up, _) | (VirtualKeyCode::Left, _) => {
+                    let previous = current.map(|current| (current + focusable.len() - 1) % focusable.len()).unwrap_or(0);
+                    self.focus = Some(focusable[previous].clone());
+                },
+                (VirtualKeyCode::Down, _) | (VirtualKeyCode::Right, _) | (VirtualKeyCode::Tab, _) => {
+                    let next = current.map(|current| (current + 1) % focusable.len()).unwrap_or(0);
+                    self.focus = Some(focusable[next].clone());
+                },
+                _ => {},
+            }
+        }
+        remaining
+    }
+
+    pub fn take_events(&mut self) -> Vec<UiEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    // Run after changing widgets and before rendering, text sizes come from the renderer
+    pub fn layout(&mut self, renderer: &Renderer) {
+        for widget in self.widgets.iter_mut().filter(|widget| widget.visible) {
+            widget.measure(renderer);
+            widget.arrange([0., 0.], [1., 1.]);
+        }
+
+        // Whatever just showed up gets focus if what had it is gone
+        let focusable = self.focusable();
+        if !self.focus.as_ref().map_or(false, |focus| focusable.contains(focus)) {
+            self.focus = focusable.first().cloned();
+        }
+    }
+
+    pub fn renderables(&self) -> (Vec<Renderable>, Vec<RenderableText>) {
+        let mut renderables = Vec::new();
+        let mut texts = Vec::new();
+        for widget in self.widgets.iter() {
+            widget.push_renderables(self.focus.as_deref(), BASE_Z, &mut renderables, &mut texts);
+        }
+        (renderables, texts)
+    }
+
+    fn focusable(&self) -> Vec<String> {
+        let mut focusable = Vec::new();
+        for widget in self.widgets.iter() {
+            widget.collect_focusable(&mut focusable);
+        }
+        focusable
+    }
+}